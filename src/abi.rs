@@ -4,5 +4,6 @@ abigen!(
     IUniswapV3Pool,
     r#"[
         function tickBitmap(int16) external returns (uint256)
+        event Swap(address indexed sender, address indexed recipient, int256 amount0, int256 amount1, uint160 sqrtPriceX96, uint128 liquidity, int24 tick)
     ]"#;
 );
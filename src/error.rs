@@ -1,11 +1,11 @@
 use thiserror::Error;
 
-// TODO: make these errors better, some errors in univ3 libs are just require(condition) without a message.
+// Messages describe the failed check, the variant names follow the Solidity revert codes where there is one
 #[derive(Error, Debug)]
 pub enum UniswapV3MathError {
     #[error("Denominator is 0")]
     DenominatorIsZero,
-    #[error("Result is U256::MAX")]
+    #[error("Rounded up result overflows U256")]
     ResultIsU256MAX,
     #[error("Sqrt price is 0")]
     SqrtPriceIsZero,
@@ -15,16 +15,13 @@ pub enum UniswapV3MathError {
     ZeroValue,
     #[error("Liquidity is 0")]
     LiquidityIsZero,
-    //TODO: Update this, shield your eyes for now
-    #[error(
-        "require((product = amount * sqrtPX96) / amount == sqrtPX96 && numerator1 > product);"
-    )]
+    #[error("Product of amount and sqrt price overflows or is not less than liquidity << 96")]
     ProductDivAmount,
     #[error("Denominator is less than or equal to prod_1")]
     DenominatorIsLteProdOne,
-    #[error("Liquidity Sub")]
+    #[error("Liquidity underflows when removing liquidity")]
     LiquiditySub,
-    #[error("Liquidity Add")]
+    #[error("Liquidity overflows when adding liquidity")]
     LiquidityAdd,
     #[error("The given tick must be less than, or equal to, the maximum tick")]
     T,
@@ -34,16 +31,125 @@ pub enum UniswapV3MathError {
     R,
     #[error("Overflow when casting to U160")]
     SafeCastToU160Overflow,
-    #[error("Tick spacing error")]
+    #[error("Tick is not a multiple of the tick spacing, or the tick spacing is not positive")]
     TickSpacingError,
-    #[error("Middleware error when getting next_initialized_tick_within_one_word")]
+    #[error("Tick spacing {0} is not positive")]
+    InvalidTickSpacing(i32),
+    #[error("Initialized tick {0} is missing from the tick data")]
+    TickNotFound(i32),
+    #[error("Price {0} is not positive and finite")]
+    InvalidPrice(f64),
+    #[error("Middleware error when getting next_initialized_tick_within_one_word: {0}")]
     MiddlewareError(String),
-    #[error("SqrtPrice Lower Than Min")]
+    #[error("Failed to decode swap log: {0}")]
+    SwapLogDecodeError(String),
+    #[error("Price is within the position range")]
+    PriceInRange,
+    #[error("Invalid hex sqrt price: {0}")]
+    InvalidHexSqrtPrice(String),
+    #[error("Sqrt price limit is not above the minimum sqrt ratio")]
     SplM,
-    #[error("SqrtPrice Lower Than Current")]
+    #[error("Sqrt price limit is not below the current sqrt price when selling token0")]
     SplC,
-    #[error("SqrtPrice Upper Than Max")]
+    #[error("Sqrt price limit is not below the maximum sqrt ratio")]
     SpuM,
-    #[error("SqrtPrice Upper Than Current")]
+    #[error("Sqrt price limit is not above the current sqrt price when selling token1")]
     SpuC,
+    #[error("Tick lower is not less than tick upper")]
+    Tlu,
+    #[error("Tick lower is less than the minimum tick")]
+    Tlm,
+    #[error("Tick upper is greater than the maximum tick")]
+    Tum,
+}
+
+#[cfg(test)]
+mod test {
+    use super::UniswapV3MathError;
+
+    #[test]
+    fn test_display() {
+        for (err, message) in [
+            (
+                UniswapV3MathError::SplM,
+                "Sqrt price limit is not above the minimum sqrt ratio",
+            ),
+            (
+                UniswapV3MathError::SplC,
+                "Sqrt price limit is not below the current sqrt price when selling token0",
+            ),
+            (
+                UniswapV3MathError::SpuM,
+                "Sqrt price limit is not below the maximum sqrt ratio",
+            ),
+            (
+                UniswapV3MathError::SpuC,
+                "Sqrt price limit is not above the current sqrt price when selling token1",
+            ),
+            (
+                UniswapV3MathError::Tlu,
+                "Tick lower is not less than tick upper",
+            ),
+            (
+                UniswapV3MathError::Tlm,
+                "Tick lower is less than the minimum tick",
+            ),
+            (
+                UniswapV3MathError::Tum,
+                "Tick upper is greater than the maximum tick",
+            ),
+            (
+                UniswapV3MathError::LiquiditySub,
+                "Liquidity underflows when removing liquidity",
+            ),
+            (
+                UniswapV3MathError::LiquidityAdd,
+                "Liquidity overflows when adding liquidity",
+            ),
+            (
+                UniswapV3MathError::ProductDivAmount,
+                "Product of amount and sqrt price overflows or is not less than liquidity << 96",
+            ),
+            (
+                UniswapV3MathError::ResultIsU256MAX,
+                "Rounded up result overflows U256",
+            ),
+            (
+                UniswapV3MathError::TickSpacingError,
+                "Tick is not a multiple of the tick spacing, or the tick spacing is not positive",
+            ),
+            (
+                UniswapV3MathError::PriceInRange,
+                "Price is within the position range",
+            ),
+        ] {
+            assert_eq!(err.to_string(), message);
+        }
+
+        //variants carrying the cause
+        assert_eq!(
+            UniswapV3MathError::MiddlewareError("timeout".to_string()).to_string(),
+            "Middleware error when getting next_initialized_tick_within_one_word: timeout"
+        );
+        assert_eq!(
+            UniswapV3MathError::SwapLogDecodeError("bad data".to_string()).to_string(),
+            "Failed to decode swap log: bad data"
+        );
+        assert_eq!(
+            UniswapV3MathError::InvalidHexSqrtPrice("0x".to_string()).to_string(),
+            "Invalid hex sqrt price: 0x"
+        );
+        assert_eq!(
+            UniswapV3MathError::InvalidTickSpacing(0).to_string(),
+            "Tick spacing 0 is not positive"
+        );
+        assert_eq!(
+            UniswapV3MathError::TickNotFound(-300).to_string(),
+            "Initialized tick -300 is missing from the tick data"
+        );
+        assert_eq!(
+            UniswapV3MathError::InvalidPrice(-1.5).to_string(),
+            "Price -1.5 is not positive and finite"
+        );
+    }
 }
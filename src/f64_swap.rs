@@ -1,11 +1,17 @@
 use crate::error::UniswapV3MathError;
-use crate::swap::TickInfo;
+use crate::liquidity_math;
+use crate::swap::{self, TickInfo};
+use crate::swap_math;
 use crate::tick_bitmap;
 use crate::tick_math;
-use ethers::prelude::U256;
+use crate::utils::{f64_to_u256, liquidity_net_to_f64, liquidity_to_f64, u256_to_f64};
+use ethers::prelude::{I256, U256};
 use hashbrown::HashMap;
 use lazy_static::lazy_static;
 
+// Powers of two are exactly representable in f64, so these constants carry no error themselves.
+// The float swap loses precision elsewhere: a sqrt price keeps 53 of its up to 160 significant
+// bits (a relative error around 1e-16, doubled in the price), and each step adds rounding on top.
 lazy_static! {
     pub static ref Q96: f64 = 2f64.powi(96);
     pub static ref Q192: f64 = 2f64.powi(192);
@@ -45,6 +51,48 @@ pub struct SwapResult {
     pub tick_after: i32,
 }
 
+// Rounding policy used when converting float amounts back to integer token amounts
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RoundingMode {
+    // towards positive infinity
+    Up,
+    // towards negative infinity
+    Down,
+    // to the nearest integer, halfway cases away from zero
+    Nearest,
+    // to the nearest integer, halfway cases to the nearest even integer
+    BankersRounding,
+}
+
+impl RoundingMode {
+    pub fn round(self, x: f64) -> f64 {
+        match self {
+            RoundingMode::Up => x.ceil(),
+            RoundingMode::Down => x.floor(),
+            RoundingMode::Nearest => x.round(),
+            RoundingMode::BankersRounding => {
+                if (x - x.trunc()).abs() == 0.5 {
+                    2f64 * (x / 2f64).round()
+                } else {
+                    x.round()
+                }
+            }
+        }
+    }
+}
+
+// Converts the human readable deltas of a float swap back to raw integer token amounts
+pub fn to_integer_amounts(
+    result: &SwapResult,
+    token0_decimals_factor: f64,
+    token1_decimals_factor: f64,
+    rounding: RoundingMode,
+) -> (I256, I256) {
+    let amount0 = rounding.round(result.amount0_delta * token0_decimals_factor);
+    let amount1 = rounding.round(result.amount1_delta * token1_decimals_factor);
+    (I256::from(amount0 as i128), I256::from(amount1 as i128))
+}
+
 pub fn swap(
     ticks: &HashMap<i32, TickInfo>,
     tick_bitmap: &HashMap<i16, U256>,
@@ -57,9 +105,50 @@ pub fn swap(
     token0_decimals_factor: f64,
     token1_decimals_factor: f64,
 ) -> Result<SwapResult, UniswapV3MathError> {
+    if tick_spacing <= 0 {
+        return Err(UniswapV3MathError::InvalidTickSpacing(tick_spacing));
+    }
     if ticks.len() == 0 {
         return Ok(SwapResult::default());
     }
+    swap_with_lookup(
+        |tick| ticks.get(&tick).cloned(),
+        |word_pos| *tick_bitmap.get(&word_pos).unwrap_or(&U256::zero()),
+        tick_spacing,
+        zero_for_one,
+        amount_specified,
+        sqrt_price_limit_x96,
+        slot0,
+        fee,
+        token0_decimals_factor,
+        token1_decimals_factor,
+    )
+}
+
+// swap() reading ticks and bitmap words through closures instead of maps, so that a simulation only
+// fetches what it goes through: get_word once per step, get_tick only for the initialized ticks crossed.
+// A crossed initialized tick get_tick does not know fails with TickNotFound, as in the integer swap.
+// Like the integer swap the loop always terminates, every step either reaches its target price or
+// exhausts the amount, and a swap too small for any output returns less than a wei of it.
+pub fn swap_with_lookup<T, W>(
+    mut get_tick: T,
+    mut get_word: W,
+    tick_spacing: i32,
+    zero_for_one: bool,
+    amount_specified: f64,
+    sqrt_price_limit_x96: f64,
+    slot0: &Slot0,
+    fee: f64,
+    token0_decimals_factor: f64,
+    token1_decimals_factor: f64,
+) -> Result<SwapResult, UniswapV3MathError>
+where
+    T: FnMut(i32) -> Option<TickInfo>,
+    W: FnMut(i16) -> U256,
+{
+    if tick_spacing <= 0 {
+        return Err(UniswapV3MathError::InvalidTickSpacing(tick_spacing));
+    }
     // if sqrt_price_limit_x96 <= tick_math::MIN_SQRT_RATIO {
     //     return Err(UniswapV3MathError::SplM);
     // }
@@ -98,7 +187,7 @@ pub fn swap(
         amount_calculated: 0f64,
         sqrt_price_x96: slot0.sqrt_price.to_string().parse::<f64>().unwrap(),
         tick: slot0.tick,
-        liquidity: slot0.liquidity as f64,
+        liquidity: liquidity_to_f64(slot0.liquidity),
     };
     let mut exhausted = false;
     loop {
@@ -118,18 +207,19 @@ pub fn swap(
         }
         let mut step = StepComputations::default();
         step.sqrt_price_start_x96 = state.sqrt_price_x96;
-        (step.tick_next, step.initialized) = tick_bitmap::next_initialized_tick_within_one_word(
-            tick_bitmap,
-            state.tick,
-            tick_spacing,
-            zero_for_one,
-        )?;
+        (step.tick_next, step.initialized) =
+            tick_bitmap::next_initialized_tick_within_one_word_with(
+                &mut get_word,
+                state.tick,
+                tick_spacing,
+                zero_for_one,
+            )?;
         if step.tick_next < tick_math::MIN_TICK {
             step.tick_next = tick_math::MIN_TICK;
         } else if step.tick_next > tick_math::MAX_TICK {
             step.tick_next = tick_math::MAX_TICK;
         }
-        step.sqrt_price_next_x96 = (1.0001f64.powi(step.tick_next) * *Q192).sqrt();
+        step.sqrt_price_next_x96 = sqrt_ratio_at_tick(step.tick_next);
         let hit_to_limit = if zero_for_one {
             // 卖出
             step.sqrt_price_next_x96 < sqrt_price_limit_x96 // 下一个tick的价格比limit低
@@ -167,7 +257,11 @@ pub fn swap(
         if state.sqrt_price_x96 == step.sqrt_price_next_x96 {
             // 如果tick初始化了，则需要更新流动性
             if step.initialized {
-                let mut l_net = ticks.get(&step.tick_next).unwrap().l_net as f64;
+                let mut l_net = liquidity_net_to_f64(
+                    get_tick(step.tick_next)
+                        .ok_or(UniswapV3MathError::TickNotFound(step.tick_next))?
+                        .l_net,
+                );
                 if zero_for_one {
                     l_net = -1f64 * l_net;
                 }
@@ -202,6 +296,230 @@ pub fn swap(
     });
 }
 
+// swap() for exactly desired_output of the output token, token1 if zero_for_one, token0 otherwise.
+// Takes the output as a positive amount and passes it on with the negative sign of exact output swaps.
+pub fn swap_exact_output(
+    ticks: &HashMap<i32, TickInfo>,
+    tick_bitmap: &HashMap<i16, U256>,
+    tick_spacing: i32,
+    zero_for_one: bool,
+    desired_output: f64,
+    sqrt_price_limit_x96: f64,
+    slot0: &Slot0,
+    fee: f64,
+    token0_decimals_factor: f64,
+    token1_decimals_factor: f64,
+) -> Result<SwapResult, UniswapV3MathError> {
+    swap(
+        ticks,
+        tick_bitmap,
+        tick_spacing,
+        zero_for_one,
+        -desired_output.abs(),
+        sqrt_price_limit_x96,
+        slot0,
+        fee,
+        token0_decimals_factor,
+        token1_decimals_factor,
+    )
+}
+
+// swap() with the price limit given as a tick, converted with sqrt_ratio_at_tick
+pub fn swap_with_tick_limit(
+    ticks: &HashMap<i32, TickInfo>,
+    tick_bitmap: &HashMap<i16, U256>,
+    tick_spacing: i32,
+    zero_for_one: bool,
+    amount_specified: f64,
+    tick_limit: i32,
+    slot0: &Slot0,
+    fee: f64,
+    token0_decimals_factor: f64,
+    token1_decimals_factor: f64,
+) -> Result<SwapResult, UniswapV3MathError> {
+    swap(
+        ticks,
+        tick_bitmap,
+        tick_spacing,
+        zero_for_one,
+        amount_specified,
+        sqrt_ratio_at_tick(tick_limit),
+        slot0,
+        fee,
+        token0_decimals_factor,
+        token1_decimals_factor,
+    )
+}
+
+// swap() that also returns the tick of its final sqrt price computed twice, in f64 from the logarithm
+// and exactly by get_tick_at_sqrt_ratio of the price converted to an integer: (result, float_tick,
+// integer_tick). Different ticks flag a final price too imprecise to place. result.tick_after is not
+// one of them, the float swap loop only moves it when crossing a tick.
+pub fn swap_with_tick_check(
+    ticks: &HashMap<i32, TickInfo>,
+    tick_bitmap: &HashMap<i16, U256>,
+    tick_spacing: i32,
+    zero_for_one: bool,
+    amount_specified: f64,
+    sqrt_price_limit_x96: f64,
+    slot0: &Slot0,
+    fee: f64,
+    token0_decimals_factor: f64,
+    token1_decimals_factor: f64,
+) -> Result<(SwapResult, i32, i32), UniswapV3MathError> {
+    let result = swap(
+        ticks,
+        tick_bitmap,
+        tick_spacing,
+        zero_for_one,
+        amount_specified,
+        sqrt_price_limit_x96,
+        slot0,
+        fee,
+        token0_decimals_factor,
+        token1_decimals_factor,
+    )?;
+    let float_tick = (2f64 * (result.sqrt_price_after / *Q96).ln() / 1.0001f64.ln()).floor() as i32;
+    let sqrt_price = f64_to_u256(result.sqrt_price_after)
+        .max(tick_math::MIN_SQRT_RATIO)
+        .min(tick_math::MAX_SQRT_RATIO - 1);
+    let integer_tick = tick_math::get_tick_at_sqrt_ratio(sqrt_price)?;
+    Ok((result, float_tick, integer_tick))
+}
+
+#[derive(Default)]
+pub struct HybridSwapResult {
+    pub amount0_delta: f64,
+    pub amount1_delta: f64,
+    pub sqrt_price_after: U256,
+    pub liquidity_after: u128,
+    pub tick_after: i32,
+}
+
+// 价格和整型swap一样用U256通过tick_math和swap_math精确计算，数量用f64累加
+// 数量都是原始数量，不做精度换算
+pub fn swap_hybrid(
+    ticks: &HashMap<i32, TickInfo>,
+    tick_bitmap: &HashMap<i16, U256>,
+    tick_spacing: i32,
+    zero_for_one: bool,
+    amount_specified: f64,
+    sqrt_price_limit_x96: U256,
+    slot0: &crate::swap::Slot0,
+    fee: u32,
+) -> Result<HybridSwapResult, UniswapV3MathError> {
+    if tick_spacing <= 0 {
+        return Err(UniswapV3MathError::InvalidTickSpacing(tick_spacing));
+    }
+    swap::check_sqrt_price_limit(zero_for_one, sqrt_price_limit_x96, slot0.sqrt_price)?;
+    let exact_input = amount_specified > 0f64;
+    let mut amount_specified_remaining = amount_specified;
+    let mut amount_calculated = 0f64;
+    let mut sqrt_price_x96 = slot0.sqrt_price;
+    let mut tick = slot0.tick;
+    let mut liquidity = slot0.liquidity;
+    // 剩余数量不足1时整型计算已经无法推动价格
+    while amount_specified_remaining.abs() >= 1f64 && sqrt_price_x96 != sqrt_price_limit_x96 {
+        let sqrt_price_start_x96 = sqrt_price_x96;
+        let (mut tick_next, initialized) = tick_bitmap::next_initialized_tick_within_one_word(
+            tick_bitmap,
+            tick,
+            tick_spacing,
+            zero_for_one,
+        )?;
+        tick_next = tick_next.clamp(tick_math::MIN_TICK, tick_math::MAX_TICK);
+        let sqrt_price_next_x96 = tick_math::get_sqrt_ratio_at_tick(tick_next)?;
+        let hit_to_limit = if zero_for_one {
+            sqrt_price_next_x96 < sqrt_price_limit_x96
+        } else {
+            sqrt_price_next_x96 > sqrt_price_limit_x96
+        };
+        let target_price = if hit_to_limit {
+            sqrt_price_limit_x96
+        } else {
+            sqrt_price_next_x96
+        };
+        // 截断为整数，超过I256范围的按I256::MAX
+        let amount_remaining =
+            f64_to_u256(amount_specified_remaining.abs()).min(I256::MAX.into_raw());
+        let amount_remaining = if exact_input {
+            I256::from_raw(amount_remaining)
+        } else {
+            -I256::from_raw(amount_remaining)
+        };
+        let (sqrt_price_after_step, amount_in, amount_out, fee_amount) =
+            swap_math::compute_swap_step(
+                sqrt_price_x96,
+                target_price,
+                liquidity,
+                amount_remaining,
+                fee,
+            )?;
+        sqrt_price_x96 = sqrt_price_after_step;
+        let amount_in = u256_to_f64(amount_in) + u256_to_f64(fee_amount);
+        let amount_out = u256_to_f64(amount_out);
+        if exact_input {
+            amount_specified_remaining -= amount_in;
+            amount_calculated -= amount_out;
+        } else {
+            amount_specified_remaining += amount_out;
+            amount_calculated += amount_in;
+        }
+        if sqrt_price_x96 == sqrt_price_next_x96 {
+            if initialized {
+                let mut l_net = ticks
+                    .get(&tick_next)
+                    .ok_or(UniswapV3MathError::TickNotFound(tick_next))?
+                    .l_net;
+                if zero_for_one {
+                    l_net = -l_net;
+                }
+                liquidity = liquidity_math::add_delta(liquidity, l_net)?;
+            }
+            tick = if zero_for_one {
+                tick_next - 1
+            } else {
+                tick_next
+            };
+        } else if sqrt_price_x96 != sqrt_price_start_x96 {
+            tick = tick_math::get_tick_at_sqrt_ratio(sqrt_price_x96)?;
+        }
+    }
+    let (amount0_delta, amount1_delta) = if zero_for_one == exact_input {
+        (
+            amount_specified - amount_specified_remaining,
+            amount_calculated,
+        )
+    } else {
+        (
+            amount_calculated,
+            amount_specified - amount_specified_remaining,
+        )
+    };
+    Ok(HybridSwapResult {
+        amount0_delta,
+        amount1_delta,
+        sqrt_price_after: sqrt_price_x96,
+        liquidity_after: liquidity,
+        tick_after: tick,
+    })
+}
+
+// Float sqrt price at a tick, the way the float swap computes it
+pub fn sqrt_ratio_at_tick(tick: i32) -> f64 {
+    (1.0001f64.powi(tick) * *Q192).sqrt()
+}
+
+// Upper bound for the relative error of sqrt_ratio_at_tick against the exact sqrt price.
+// 1.0001 is not exact in f64 and powi gets to the exponent by repeated squaring, so the error of the
+// price grows linearly with |tick|, plus a rounding per multiplication. Taking the square root halves
+// it. That is ~2e-16 at tick 0, ~1e-13 at tick ±1000 and ~1e-10 at the tick extremes.
+pub fn max_price_error_at_tick(tick: i32) -> f64 {
+    let ticks = tick.unsigned_abs();
+    let multiplications = 32 - ticks.leading_zeros();
+    (ticks + multiplications + 2) as f64 * f64::EPSILON / 2f64
+}
+
 // 最后一个值表示amount_remaining是否耗尽了
 // 由于是浮点计算，外层扣除amount_remaining时会有误差，所以这个方法额外返回一个bool值辅助外层调用，判断是否应该结束swap
 fn compute_swap_step(
@@ -289,15 +607,29 @@ fn compute_swap_step(
     return (sqrt_p_next, amount_in, amount_out, fee_amount, exhausted);
 }
 
+// 和整型的_get_amount_0_delta一样，两个价格的顺序可以颠倒，结果总是非负的
 fn get_amount0_delta(sqrt_price_lower: f64, sqrt_price_upper: f64, sqrt_l: f64) -> f64 {
+    let (sqrt_price_lower, sqrt_price_upper) =
+        order_sqrt_prices(sqrt_price_lower, sqrt_price_upper);
     return sqrt_l * *Q96 * (sqrt_price_upper - sqrt_price_lower)
         / (sqrt_price_lower * sqrt_price_upper);
 }
 
+// 和整型的_get_amount_1_delta一样，两个价格的顺序可以颠倒，结果总是非负的
 fn get_amount1_delta(sqrt_price_lower: f64, sqrt_price_upper: f64, sqrt_l: f64) -> f64 {
+    let (sqrt_price_lower, sqrt_price_upper) =
+        order_sqrt_prices(sqrt_price_lower, sqrt_price_upper);
     return sqrt_l * (sqrt_price_upper - sqrt_price_lower) / *Q96;
 }
 
+fn order_sqrt_prices(sqrt_price_a: f64, sqrt_price_b: f64) -> (f64, f64) {
+    if sqrt_price_a > sqrt_price_b {
+        (sqrt_price_b, sqrt_price_a)
+    } else {
+        (sqrt_price_a, sqrt_price_b)
+    }
+}
+
 fn get_sqrt_price_from_input(
     zero_for_one: bool,
     amount_in: f64,
@@ -333,3 +665,518 @@ fn get_sqrt_price_from_output(
         return sqrt_l * sqrt_price_current / (sqrt_l - amount_out * sqrt_price_current);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use ethers::types::{I256, U256};
+
+    use crate::{
+        error::UniswapV3MathError,
+        sqrt_price_math,
+        swap::{
+            default_sqrt_price_limit,
+            test::{pool_with_positions, test_pool},
+        },
+        tick_math::{self, get_sqrt_ratio_at_tick},
+        utils::u256_to_f64,
+    };
+
+    use super::{
+        get_amount0_delta, get_amount1_delta, max_price_error_at_tick, sqrt_ratio_at_tick, swap,
+        swap_exact_output, swap_hybrid, swap_with_lookup, swap_with_tick_check,
+        swap_with_tick_limit, to_integer_amounts, RoundingMode, Slot0, SwapResult, Q192, Q96,
+    };
+
+    #[test]
+    fn test_to_integer_amounts() {
+        let result = SwapResult {
+            amount0_delta: 2.5,
+            amount1_delta: -3.5,
+            ..Default::default()
+        };
+
+        let (amount0, amount1) = to_integer_amounts(&result, 1f64, 1f64, RoundingMode::Up);
+        assert_eq!((amount0, amount1), (I256::from(3), I256::from(-3)));
+
+        let (amount0, amount1) = to_integer_amounts(&result, 1f64, 1f64, RoundingMode::Down);
+        assert_eq!((amount0, amount1), (I256::from(2), I256::from(-4)));
+
+        let (amount0, amount1) = to_integer_amounts(&result, 1f64, 1f64, RoundingMode::Nearest);
+        assert_eq!((amount0, amount1), (I256::from(3), I256::from(-4)));
+
+        //halfway cases go to the even neighbour
+        let (amount0, amount1) =
+            to_integer_amounts(&result, 1f64, 1f64, RoundingMode::BankersRounding);
+        assert_eq!((amount0, amount1), (I256::from(2), I256::from(-4)));
+
+        //decimals factors are applied before rounding
+        let result = SwapResult {
+            amount0_delta: 0.0000000000000005,
+            amount1_delta: 1.5,
+            ..Default::default()
+        };
+        let (amount0, amount1) =
+            to_integer_amounts(&result, 1e18, 1f64, RoundingMode::BankersRounding);
+        assert_eq!((amount0, amount1), (I256::from(500), I256::from(2)));
+
+        //non halfway values round to the nearest integer
+        assert_eq!(RoundingMode::BankersRounding.round(2.6), 3f64);
+        assert_eq!(RoundingMode::BankersRounding.round(-2.4), -2f64);
+    }
+
+    #[test]
+    fn test_amount_delta_reversed_bounds() {
+        let sqrt_price_lower = *Q96;
+        let sqrt_price_upper = *Q96 * 1.1f64;
+        let liquidity = 1e18;
+
+        let amount0 = get_amount0_delta(sqrt_price_lower, sqrt_price_upper, liquidity);
+        assert!(amount0 > 0f64);
+        assert_eq!(
+            get_amount0_delta(sqrt_price_upper, sqrt_price_lower, liquidity),
+            amount0
+        );
+
+        let amount1 = get_amount1_delta(sqrt_price_lower, sqrt_price_upper, liquidity);
+        assert!(amount1 > 0f64);
+        assert_eq!(
+            get_amount1_delta(sqrt_price_upper, sqrt_price_lower, liquidity),
+            amount1
+        );
+    }
+
+    #[test]
+    fn test_q96_q192_are_exact() {
+        assert_eq!(*Q96, u256_to_f64(sqrt_price_math::Q96));
+        assert_eq!(*Q96 as u128, 1u128 << 96);
+        assert_eq!(*Q192, *Q96 * *Q96);
+        assert_eq!(Q192.log2(), 192f64);
+        //a single bit set in the mantissa
+        assert_eq!(Q96.to_bits() & ((1u64 << 52) - 1), 0);
+        assert_eq!(Q192.to_bits() & ((1u64 << 52) - 1), 0);
+    }
+
+    #[test]
+    fn test_swap_hybrid() {
+        //crosses tick -300 and stops around tick -494
+        let pool = test_pool();
+        let expected = pool
+            .swap(
+                true,
+                I256::from_dec_str("30000000000000000").unwrap(),
+                default_sqrt_price_limit(true),
+                3000,
+            )
+            .unwrap();
+
+        let hybrid = swap_hybrid(
+            &pool.ticks,
+            &pool.tick_bitmap,
+            pool.tick_spacing,
+            true,
+            3e16,
+            default_sqrt_price_limit(true),
+            &pool.slot0,
+            3000,
+        )
+        .unwrap();
+        assert_eq!(hybrid.tick_after, expected.tick_after);
+        assert_eq!(hybrid.liquidity_after, expected.liquidity_after);
+        let expected_amount1 = expected.amount1_delta.low_i128() as f64;
+        assert!((hybrid.amount1_delta - expected_amount1).abs() / expected_amount1.abs() < 1e-9);
+
+        let float = swap(
+            &pool.ticks,
+            &pool.tick_bitmap,
+            pool.tick_spacing,
+            true,
+            3e16,
+            u256_to_f64(default_sqrt_price_limit(true)),
+            &Slot0 {
+                sqrt_price: u256_to_f64(pool.slot0.sqrt_price),
+                liquidity: pool.slot0.liquidity,
+                tick: pool.slot0.tick,
+            },
+            0.003,
+            1f64,
+            1f64,
+        )
+        .unwrap();
+        assert!(
+            (hybrid.tick_after - expected.tick_after).abs()
+                <= (float.tick_after - expected.tick_after).abs()
+        );
+    }
+
+    #[test]
+    fn test_max_price_error_at_tick() {
+        let mut tick = tick_math::MIN_TICK;
+        while tick <= tick_math::MAX_TICK {
+            let exact = get_sqrt_ratio_at_tick(tick).unwrap();
+            let exact_f64 = u256_to_f64(exact);
+            let error = (sqrt_ratio_at_tick(tick) - exact_f64).abs() / exact_f64;
+            //the integer sqrt price is rounded to an integer, and converting it rounds once more
+            let allowed = max_price_error_at_tick(tick) + 1f64 / exact_f64 + f64::EPSILON;
+            assert!(error <= allowed, "tick {}: {} > {}", tick, error, allowed);
+            tick += 4999;
+        }
+
+        //grows towards the tick extremes
+        assert!(max_price_error_at_tick(0) < 3e-16);
+        assert!(max_price_error_at_tick(-1000) < 2e-13);
+        assert!(max_price_error_at_tick(tick_math::MAX_TICK) < 1e-10);
+        assert_eq!(
+            max_price_error_at_tick(tick_math::MIN_TICK),
+            max_price_error_at_tick(tick_math::MAX_TICK)
+        );
+    }
+
+    #[test]
+    fn test_swap_exact_output() {
+        let pool = test_pool();
+        let slot0 = Slot0 {
+            sqrt_price: u256_to_f64(pool.slot0.sqrt_price),
+            liquidity: pool.slot0.liquidity,
+            tick: pool.slot0.tick,
+        };
+        for zero_for_one in [true, false] {
+            let float = swap_exact_output(
+                &pool.ticks,
+                &pool.tick_bitmap,
+                pool.tick_spacing,
+                zero_for_one,
+                1e16,
+                u256_to_f64(default_sqrt_price_limit(zero_for_one)),
+                &slot0,
+                0.003,
+                1f64,
+                1f64,
+            )
+            .unwrap();
+            let expected = pool
+                .swap(
+                    zero_for_one,
+                    I256::from(-1e16 as i64),
+                    default_sqrt_price_limit(zero_for_one),
+                    3000,
+                )
+                .unwrap();
+
+            let (output, input) = if zero_for_one {
+                (float.amount1_delta, float.amount0_delta)
+            } else {
+                (float.amount0_delta, float.amount1_delta)
+            };
+            let expected_input = if zero_for_one {
+                expected.amount0_delta
+            } else {
+                expected.amount1_delta
+            }
+            .low_i128() as f64;
+            assert!((output + 1e16).abs() < 1f64);
+            assert!((input - expected_input).abs() / expected_input < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_swap_invalid_tick_spacing() {
+        let pool = test_pool();
+        let result = swap(
+            &pool.ticks,
+            &pool.tick_bitmap,
+            0,
+            true,
+            1e15,
+            u256_to_f64(default_sqrt_price_limit(true)),
+            &Slot0 {
+                sqrt_price: u256_to_f64(pool.slot0.sqrt_price),
+                liquidity: pool.slot0.liquidity,
+                tick: pool.slot0.tick,
+            },
+            0.003,
+            1f64,
+            1f64,
+        );
+        assert!(matches!(
+            result,
+            Err(UniswapV3MathError::InvalidTickSpacing(0))
+        ));
+    }
+
+    #[test]
+    fn test_swap_with_tick_limit() {
+        let pool = test_pool();
+        let slot0 = Slot0 {
+            sqrt_price: u256_to_f64(pool.slot0.sqrt_price),
+            liquidity: pool.slot0.liquidity,
+            tick: pool.slot0.tick,
+        };
+        let swap_to = |tick_limit: i32| {
+            swap_with_tick_limit(
+                &pool.ticks,
+                &pool.tick_bitmap,
+                pool.tick_spacing,
+                true,
+                3e16,
+                tick_limit,
+                &slot0,
+                0.003,
+                1f64,
+                1f64,
+            )
+            .unwrap()
+        };
+
+        //stops at the limit, before crossing tick -300
+        let limited = swap_to(-200);
+        assert_eq!(limited.sqrt_price_after, sqrt_ratio_at_tick(-200));
+        assert!(limited.amount0_delta < 3e16);
+        let expected = swap(
+            &pool.ticks,
+            &pool.tick_bitmap,
+            pool.tick_spacing,
+            true,
+            3e16,
+            sqrt_ratio_at_tick(-200),
+            &slot0,
+            0.003,
+            1f64,
+            1f64,
+        )
+        .unwrap();
+        assert_eq!(limited.amount0_delta, expected.amount0_delta);
+        assert_eq!(limited.amount1_delta, expected.amount1_delta);
+
+        //a limit beyond the swap does not bind
+        let unlimited = swap_to(tick_math::MIN_TICK);
+        assert!((unlimited.amount0_delta - 3e16).abs() < 1f64);
+        assert!(unlimited.sqrt_price_after < sqrt_ratio_at_tick(-300));
+    }
+
+    #[test]
+    fn test_swap_with_tick_check() {
+        let pool = test_pool();
+        let slot0 = Slot0 {
+            sqrt_price: u256_to_f64(pool.slot0.sqrt_price),
+            liquidity: pool.slot0.liquidity,
+            tick: pool.slot0.tick,
+        };
+        let expected = pool
+            .swap(
+                true,
+                I256::from_dec_str("30000000000000000").unwrap(),
+                default_sqrt_price_limit(true),
+                3000,
+            )
+            .unwrap();
+
+        //ends around tick -493.44, far from a tick boundary
+        let (result, float_tick, integer_tick) = swap_with_tick_check(
+            &pool.ticks,
+            &pool.tick_bitmap,
+            pool.tick_spacing,
+            true,
+            3e16,
+            u256_to_f64(default_sqrt_price_limit(true)),
+            &slot0,
+            0.003,
+            1f64,
+            1f64,
+        )
+        .unwrap();
+        assert_eq!(float_tick, integer_tick);
+        assert_eq!(integer_tick, expected.tick_after);
+        //the loop's own tick is only updated at the crossing of -300
+        assert_eq!(result.tick_after, -301);
+    }
+
+    #[test]
+    fn test_swap_one_wei() {
+        let pool = pool_with_positions(&[(-600, 600, 1e24 as u128)], 60, 0);
+        let slot0 = Slot0 {
+            sqrt_price: u256_to_f64(pool.slot0.sqrt_price),
+            liquidity: pool.slot0.liquidity,
+            tick: pool.slot0.tick,
+        };
+        for zero_for_one in [true, false] {
+            let result = swap(
+                &pool.ticks,
+                &pool.tick_bitmap,
+                pool.tick_spacing,
+                zero_for_one,
+                1f64,
+                u256_to_f64(default_sqrt_price_limit(zero_for_one)),
+                &slot0,
+                0.003,
+                1f64,
+                1f64,
+            )
+            .unwrap();
+            let (input, output) = if zero_for_one {
+                (result.amount0_delta, result.amount1_delta)
+            } else {
+                (result.amount1_delta, result.amount0_delta)
+            };
+            //less than a wei out, which rounds to zero towards the pool like the integer swap
+            assert!((input - 1f64).abs() < 1e-12);
+            assert!(output <= 0f64 && output > -1f64);
+            let (amount0, amount1) = to_integer_amounts(&result, 1f64, 1f64, RoundingMode::Up);
+            let amount_out = if zero_for_one { amount1 } else { amount0 };
+            assert!(amount_out.is_zero());
+        }
+    }
+
+    #[test]
+    fn test_swap_with_lookup() {
+        let pool = test_pool();
+        let slot0 = Slot0 {
+            sqrt_price: u256_to_f64(pool.slot0.sqrt_price),
+            liquidity: pool.slot0.liquidity,
+            tick: pool.slot0.tick,
+        };
+        let limit = u256_to_f64(default_sqrt_price_limit(true));
+
+        //crosses tick -300 only
+        let mut tick_reads = vec![];
+        let mut word_reads = vec![];
+        let result = swap_with_lookup(
+            |tick| {
+                tick_reads.push(tick);
+                pool.ticks.get(&tick).cloned()
+            },
+            |word_pos| {
+                word_reads.push(word_pos);
+                *pool.tick_bitmap.get(&word_pos).unwrap_or(&U256::zero())
+            },
+            pool.tick_spacing,
+            true,
+            3e16,
+            limit,
+            &slot0,
+            0.003,
+            1f64,
+            1f64,
+        )
+        .unwrap();
+        assert_eq!(tick_reads, vec![-300]);
+        //the uninitialized word boundary at tick 0, then the word below it
+        assert_eq!(word_reads, vec![0, -1, -1]);
+
+        let expected = swap(
+            &pool.ticks,
+            &pool.tick_bitmap,
+            pool.tick_spacing,
+            true,
+            3e16,
+            limit,
+            &slot0,
+            0.003,
+            1f64,
+            1f64,
+        )
+        .unwrap();
+        assert_eq!(result.amount1_delta, expected.amount1_delta);
+        assert_eq!(result.liquidity_after, expected.liquidity_after);
+
+        //a lookup missing the crossed tick -300 fails instead of swapping on with the old liquidity
+        let result = swap_with_lookup(
+            |tick| pool.ticks.get(&tick).cloned().filter(|_| tick != -300),
+            |word_pos| *pool.tick_bitmap.get(&word_pos).unwrap_or(&U256::zero()),
+            pool.tick_spacing,
+            true,
+            3e16,
+            limit,
+            &slot0,
+            0.003,
+            1f64,
+            1f64,
+        );
+        assert!(matches!(
+            result,
+            Err(UniswapV3MathError::TickNotFound(-300))
+        ));
+    }
+
+    #[test]
+    fn test_swap_hybrid_missing_tick() {
+        //the bitmap still marks tick -300, which the tick map no longer has
+        let pool = test_pool();
+        let mut ticks = pool.ticks.clone();
+        ticks.remove(&-300);
+        let result = swap_hybrid(
+            &ticks,
+            &pool.tick_bitmap,
+            pool.tick_spacing,
+            true,
+            3e16,
+            default_sqrt_price_limit(true),
+            &pool.slot0,
+            3000,
+        );
+        assert!(matches!(
+            result,
+            Err(UniswapV3MathError::TickNotFound(-300))
+        ));
+    }
+
+    #[test]
+    fn test_swap_hybrid_rejects_invalid_params() {
+        let pool = test_pool();
+        let hybrid = |tick_spacing, zero_for_one, sqrt_price_limit| {
+            swap_hybrid(
+                &pool.ticks,
+                &pool.tick_bitmap,
+                tick_spacing,
+                zero_for_one,
+                1e15,
+                sqrt_price_limit,
+                &pool.slot0,
+                3000,
+            )
+        };
+
+        assert!(matches!(
+            hybrid(0, true, default_sqrt_price_limit(true)),
+            Err(UniswapV3MathError::InvalidTickSpacing(0))
+        ));
+        //a limit on the wrong side of the price does not swap the other way
+        assert!(matches!(
+            hybrid(60, true, default_sqrt_price_limit(false)),
+            Err(UniswapV3MathError::SplC)
+        ));
+        assert!(matches!(
+            hybrid(60, false, default_sqrt_price_limit(true)),
+            Err(UniswapV3MathError::SpuC)
+        ));
+        assert!(matches!(
+            hybrid(60, true, tick_math::MIN_SQRT_RATIO),
+            Err(UniswapV3MathError::SplM)
+        ));
+        assert!(matches!(
+            hybrid(60, false, tick_math::MAX_SQRT_RATIO),
+            Err(UniswapV3MathError::SpuM)
+        ));
+    }
+
+    #[test]
+    fn test_swap_hybrid_beyond_i128() {
+        //more than i128::MAX of input swaps up to the limit like an I256::MAX integer swap
+        let pool = test_pool();
+        let expected = pool
+            .swap(true, I256::MAX, default_sqrt_price_limit(true), 3000)
+            .unwrap();
+        let hybrid = swap_hybrid(
+            &pool.ticks,
+            &pool.tick_bitmap,
+            pool.tick_spacing,
+            true,
+            1e40,
+            default_sqrt_price_limit(true),
+            &pool.slot0,
+            3000,
+        )
+        .unwrap();
+        assert_eq!(hybrid.sqrt_price_after, expected.sqrt_price_after);
+        assert_eq!(hybrid.liquidity_after, expected.liquidity_after);
+    }
+}
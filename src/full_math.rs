@@ -163,11 +163,11 @@ mod tests {
 #[cfg(test)]
 mod test {
 
-    use std::ops::{Div, Mul, Sub};
+    use std::ops::{Add, Div, Mul, Sub};
 
-    use ethers::types::U256;
+    use ethers::types::{U256, U512};
 
-    use super::mul_div;
+    use super::{mul_div, mul_div_rounding_up};
 
     const Q128: U256 = U256([0, 0, 1, 0]);
 
@@ -218,4 +218,46 @@ mod test {
         let result = mul_div(Q128, U256::from(1000).mul(Q128), U256::from(3000).mul(Q128));
         assert_eq!(result.unwrap(), Q128.div(3));
     }
+
+    #[test]
+    fn test_mul_div_512_bit_product() {
+        // a * b overflows uint256 but the quotient fits, with an even and an odd denominator
+        let result = mul_div(U256::MAX, U256::MAX.sub(1), U256::MAX);
+        assert_eq!(result.unwrap(), U256::MAX.sub(1));
+
+        let result = mul_div(U256::exp10(70), U256::exp10(70), U256::exp10(65));
+        assert_eq!(result.unwrap(), U256::exp10(75));
+
+        let a = U256::exp10(76).add(7);
+        let b = U256::from(3).mul(U256::exp10(76));
+        let denominator = U256::from(7).mul(U256::exp10(75)).add(1);
+        let expected = U256::from_dec_str(
+            "42857142857142857142857142857142857142857142857142857142857142857142857142881",
+        )
+        .unwrap();
+        assert_eq!(mul_div(a, b, denominator).unwrap(), expected);
+        // the product is not a multiple of the denominator
+        assert_eq!(
+            mul_div_rounding_up(a, b, denominator).unwrap(),
+            expected.add(1)
+        );
+
+        // agrees with a plain 512-bit division
+        for (a, b, denominator) in [
+            (U256::MAX, U256::MAX.div(2), U256::MAX.sub(1)),
+            (Q128.sub(1), Q128.add(1), Q128.add(1)),
+            (U256::MAX.div(3), U256::from(1000), U256::from(999)),
+            (a, b.div(7), denominator.div(3)),
+        ] {
+            let expected = U256::try_from(a.full_mul(b) / U512::from(denominator)).unwrap();
+            assert_eq!(mul_div(a, b, denominator).unwrap(), expected);
+        }
+
+        // the quotient needs more than 256 bits
+        let result = mul_div(U256::MAX, U256::from(2), U256::one());
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "Denominator is less than or equal to prod_1"
+        );
+    }
 }
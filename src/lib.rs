@@ -2,7 +2,9 @@ pub mod abi;
 pub mod bit_math;
 pub mod error;
 pub mod full_math;
+pub mod liquidity_amounts;
 pub mod liquidity_math;
+pub mod oracle;
 pub mod sqrt_price_math;
 pub mod swap_math;
 pub mod tick;
@@ -12,6 +14,7 @@ pub mod unsafe_math;
 pub mod utils;
 
 // 整型运算
+pub mod route;
 pub mod swap;
 // 浮点运算
 pub mod f64_swap;
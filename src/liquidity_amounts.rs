@@ -0,0 +1,843 @@
+use ethers::types::{U256, U512};
+
+use crate::{
+    error::UniswapV3MathError,
+    full_math::{mul_div, mul_div_rounding_up},
+    sqrt_price_math::{_get_amount_0_delta, _get_amount_1_delta, Q96},
+    tick_math::{get_sqrt_ratio_at_tick, get_tick_at_sqrt_ratio, MAX_TICK, MIN_TICK},
+    utils::{f64_to_u256, sqrt_u256, u256_to_f64},
+};
+
+// returns (uint160 sqrtQX96)
+// Solves for the sqrt price in [sqrt_ratio_a_x_96, sqrt_ratio_b_x_96] at which a position holds
+// token0 and token1 in the ratio target_amount_0 : target_amount_1. The ratio does not depend on
+// liquidity, and amount1 / amount0 grows monotonically with the price, so the price is found by
+// bisection against the amounts of a u128::MAX liquidity position.
+pub fn sqrt_price_for_ratio(
+    mut sqrt_ratio_a_x_96: U256,
+    mut sqrt_ratio_b_x_96: U256,
+    target_amount_0: U256,
+    target_amount_1: U256,
+) -> Result<U256, UniswapV3MathError> {
+    if sqrt_ratio_a_x_96 > sqrt_ratio_b_x_96 {
+        (sqrt_ratio_a_x_96, sqrt_ratio_b_x_96) = (sqrt_ratio_b_x_96, sqrt_ratio_a_x_96)
+    };
+
+    if sqrt_ratio_a_x_96.is_zero() {
+        return Err(UniswapV3MathError::SqrtPriceIsZero);
+    }
+
+    // only token1 wanted, the position must be at or above the upper bound
+    if target_amount_0.is_zero() {
+        return Ok(sqrt_ratio_b_x_96);
+    }
+    // only token0 wanted, the position must be at or below the lower bound
+    if target_amount_1.is_zero() {
+        return Ok(sqrt_ratio_a_x_96);
+    }
+
+    let mut low = sqrt_ratio_a_x_96;
+    let mut high = sqrt_ratio_b_x_96;
+
+    while high - low > U256::one() {
+        let mid = (low + high) >> 1;
+
+        let amount_0 = _get_amount_0_delta(mid, sqrt_ratio_b_x_96, u128::MAX, false)?;
+        let amount_1 = _get_amount_1_delta(sqrt_ratio_a_x_96, mid, u128::MAX, false)?;
+
+        // amount_1 / amount_0 < target_amount_1 / target_amount_0, compared in 512 bits
+        if amount_1.full_mul(target_amount_0) < amount_0.full_mul(target_amount_1) {
+            low = mid;
+        } else {
+            high = mid;
+        }
+    }
+
+    Ok(low)
+}
+
+// returns (bool isToken0, uint256 amount)
+// The single token a position needs while the price is outside of its range: token0 at or below the
+// lower bound, token1 at or above the upper bound. Rounded up, as when minting.
+pub fn one_sided_amount(
+    sqrt_price_x_96: U256,
+    mut sqrt_ratio_a_x_96: U256,
+    mut sqrt_ratio_b_x_96: U256,
+    liquidity: u128,
+) -> Result<(bool, U256), UniswapV3MathError> {
+    if sqrt_ratio_a_x_96 > sqrt_ratio_b_x_96 {
+        (sqrt_ratio_a_x_96, sqrt_ratio_b_x_96) = (sqrt_ratio_b_x_96, sqrt_ratio_a_x_96)
+    };
+
+    if sqrt_price_x_96 <= sqrt_ratio_a_x_96 {
+        Ok((
+            true,
+            _get_amount_0_delta(sqrt_ratio_a_x_96, sqrt_ratio_b_x_96, liquidity, true)?,
+        ))
+    } else if sqrt_price_x_96 >= sqrt_ratio_b_x_96 {
+        Ok((
+            false,
+            _get_amount_1_delta(sqrt_ratio_a_x_96, sqrt_ratio_b_x_96, liquidity, true)?,
+        ))
+    } else {
+        Err(UniswapV3MathError::PriceInRange)
+    }
+}
+
+// Where the price is relative to a position, with the distance in ticks to the nearest bound
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RangeStatus {
+    InRange,
+    BelowRange(i32),
+    AboveRange(i32),
+}
+
+// The range is active from its lower bound up to, not including, its upper bound, as in the pool
+pub fn range_status(
+    sqrt_price_current_x_96: U256,
+    mut sqrt_ratio_a_x_96: U256,
+    mut sqrt_ratio_b_x_96: U256,
+) -> Result<RangeStatus, UniswapV3MathError> {
+    if sqrt_ratio_a_x_96 > sqrt_ratio_b_x_96 {
+        (sqrt_ratio_a_x_96, sqrt_ratio_b_x_96) = (sqrt_ratio_b_x_96, sqrt_ratio_a_x_96)
+    };
+
+    if sqrt_price_current_x_96 < sqrt_ratio_a_x_96 {
+        Ok(RangeStatus::BelowRange(
+            get_tick_at_sqrt_ratio(sqrt_ratio_a_x_96)?
+                - get_tick_at_sqrt_ratio(sqrt_price_current_x_96)?,
+        ))
+    } else if sqrt_price_current_x_96 >= sqrt_ratio_b_x_96 {
+        Ok(RangeStatus::AboveRange(
+            get_tick_at_sqrt_ratio(sqrt_price_current_x_96)?
+                - get_tick_at_sqrt_ratio(sqrt_ratio_b_x_96)?,
+        ))
+    } else {
+        Ok(RangeStatus::InRange)
+    }
+}
+
+// returns (int24 tickLower, int24 tickUpper)
+// The narrowest tick_spacing aligned range holding every price from forecast_low to forecast_high, and
+// the current price, so that the position starts out in range. Prices are of token0 in token1,
+// adjusted for the decimals, and must be positive and finite. Kept within the ticks usable at
+// tick_spacing.
+pub fn optimal_range(
+    current_price: f64,
+    forecast_low: f64,
+    forecast_high: f64,
+    decimals_0: u8,
+    decimals_1: u8,
+    tick_spacing: i32,
+) -> Result<(i32, i32), UniswapV3MathError> {
+    if tick_spacing <= 0 {
+        return Err(UniswapV3MathError::InvalidTickSpacing(tick_spacing));
+    }
+    for price in [current_price, forecast_low, forecast_high] {
+        if !(price.is_finite() && price > 0f64) {
+            return Err(UniswapV3MathError::InvalidPrice(price));
+        }
+    }
+    let decimals_factor = 10f64.powi(decimals_1 as i32 - decimals_0 as i32);
+    let tick_of = |price: f64| (price * decimals_factor).ln() / 1.0001f64.ln();
+    let low = forecast_low.min(forecast_high).min(current_price);
+    let high = forecast_low.max(forecast_high).max(current_price);
+
+    let min_tick = (MIN_TICK / tick_spacing) * tick_spacing;
+    let max_tick = (MAX_TICK / tick_spacing) * tick_spacing;
+    let tick_lower = (tick_of(low).floor() as i32).div_euclid(tick_spacing) * tick_spacing;
+    let tick_upper = -(-(tick_of(high).ceil() as i32)).div_euclid(tick_spacing) * tick_spacing;
+    let tick_lower = tick_lower.clamp(min_tick, max_tick - tick_spacing);
+    let tick_upper = tick_upper.clamp(tick_lower + tick_spacing, max_tick);
+    Ok((tick_lower, tick_upper))
+}
+
+// The least active liquidity that keeps the price move of swapping amount (net of the fee) under
+// max_impact_bps of the price, assuming the swap stays within one range. With k the allowed sqrt price
+// ratio, selling token0 needs L = amount * sqrt_price * k / (Q96 * (Q96 - k)) and selling token1
+// L = amount * Q96 * Q96 / (sqrt_price * (k - Q96)), rounded up. Saturates at u128::MAX.
+pub fn liquidity_for_swap_impact(
+    amount: U256,
+    max_impact_bps: u32,
+    sqrt_price_x_96: U256,
+    zero_for_one: bool,
+) -> u128 {
+    if amount.is_zero() || (zero_for_one && max_impact_bps >= 10000) {
+        return 0;
+    }
+    if max_impact_bps == 0 || sqrt_price_x_96.is_zero() {
+        return u128::MAX;
+    }
+
+    let bps = if zero_for_one {
+        10000 - max_impact_bps
+    } else {
+        10000 + max_impact_bps
+    };
+    // sqrt(bps / 10000) in Q96
+    let k = sqrt_u256((U256::from(bps) << 192) / U256::from(10000));
+    let liquidity = if zero_for_one {
+        mul_div_rounding_up(amount, sqrt_price_x_96, Q96 - k)
+            .and_then(|l| mul_div_rounding_up(l, k, Q96))
+    } else {
+        mul_div_rounding_up(amount, Q96, sqrt_price_x_96)
+            .and_then(|l| mul_div_rounding_up(l, Q96, k - Q96))
+    };
+    match liquidity {
+        Ok(liquidity) if liquidity <= U256::from(u128::MAX) => liquidity.as_u128(),
+        _ => u128::MAX,
+    }
+}
+
+// returns (uint256 amount0, uint256 amount1)
+// Tokens to pay for minting liquidity at the current sqrt price, rounded up and split on the current
+// tick as in UniswapV3Pool._modifyPosition
+pub fn mint_amounts(
+    sqrt_price_current_x_96: U256,
+    tick_lower: i32,
+    tick_upper: i32,
+    liquidity: u128,
+) -> Result<(U256, U256), UniswapV3MathError> {
+    exit_ticks(tick_lower, tick_upper)?;
+    let tick = get_tick_at_sqrt_ratio(sqrt_price_current_x_96)?;
+    let sqrt_ratio_a_x_96 = get_sqrt_ratio_at_tick(tick_lower)?;
+    let sqrt_ratio_b_x_96 = get_sqrt_ratio_at_tick(tick_upper)?;
+
+    if tick < tick_lower {
+        Ok((
+            _get_amount_0_delta(sqrt_ratio_a_x_96, sqrt_ratio_b_x_96, liquidity, true)?,
+            U256::zero(),
+        ))
+    } else if tick < tick_upper {
+        Ok((
+            _get_amount_0_delta(sqrt_price_current_x_96, sqrt_ratio_b_x_96, liquidity, true)?,
+            _get_amount_1_delta(sqrt_ratio_a_x_96, sqrt_price_current_x_96, liquidity, true)?,
+        ))
+    } else {
+        Ok((
+            U256::zero(),
+            _get_amount_1_delta(sqrt_ratio_a_x_96, sqrt_ratio_b_x_96, liquidity, true)?,
+        ))
+    }
+}
+
+// returns (uint256 amount0, uint256 amount1, uint128 liquidity)
+// The most liquidity mintable from budget_0 and budget_1 at the current sqrt price, as
+// LiquidityAmounts.getLiquidityForAmounts computes it, with the amounts mint_amounts() charges for it.
+// The budget left over is budget minus amount. The liquidity is lowered, by a wei or two at most, where
+// rounding up the amounts would make them exceed the budget.
+pub fn amounts_for_budget(
+    budget_0: U256,
+    budget_1: U256,
+    sqrt_price_current_x_96: U256,
+    tick_lower: i32,
+    tick_upper: i32,
+) -> Result<(U256, U256, u128), UniswapV3MathError> {
+    exit_ticks(tick_lower, tick_upper)?;
+    let tick = get_tick_at_sqrt_ratio(sqrt_price_current_x_96)?;
+    let sqrt_ratio_a_x_96 = get_sqrt_ratio_at_tick(tick_lower)?;
+    let sqrt_ratio_b_x_96 = get_sqrt_ratio_at_tick(tick_upper)?;
+
+    // getLiquidityForAmount0 and getLiquidityForAmount1, u128::MAX where no amount is needed
+    let liquidity_for_amount_0 = |sqrt_ratio_a: U256, sqrt_ratio_b: U256| {
+        if sqrt_ratio_b <= sqrt_ratio_a {
+            return Ok(U256::from(u128::MAX));
+        }
+        let intermediate = mul_div(sqrt_ratio_a, sqrt_ratio_b, Q96)?;
+        mul_div(budget_0, intermediate, sqrt_ratio_b - sqrt_ratio_a)
+    };
+    let liquidity_for_amount_1 = |sqrt_ratio_a: U256, sqrt_ratio_b: U256| {
+        if sqrt_ratio_b <= sqrt_ratio_a {
+            return Ok(U256::from(u128::MAX));
+        }
+        mul_div(budget_1, Q96, sqrt_ratio_b - sqrt_ratio_a)
+    };
+    let liquidity = if tick < tick_lower {
+        liquidity_for_amount_0(sqrt_ratio_a_x_96, sqrt_ratio_b_x_96)?
+    } else if tick < tick_upper {
+        liquidity_for_amount_0(sqrt_price_current_x_96, sqrt_ratio_b_x_96)?.min(
+            liquidity_for_amount_1(sqrt_ratio_a_x_96, sqrt_price_current_x_96)?,
+        )
+    } else {
+        liquidity_for_amount_1(sqrt_ratio_a_x_96, sqrt_ratio_b_x_96)?
+    };
+    let mut liquidity = liquidity.min(U256::from(u128::MAX)).as_u128();
+
+    loop {
+        let (amount_0, amount_1) =
+            mint_amounts(sqrt_price_current_x_96, tick_lower, tick_upper, liquidity)?;
+        if (amount_0 <= budget_0 && amount_1 <= budget_1) || liquidity == 0 {
+            return Ok((amount_0, amount_1, liquidity));
+        }
+        liquidity -= 1;
+    }
+}
+
+// returns (int24 tickToken0, int24 tickToken1)
+// The ticks at which a position turns single sided: at or below tick_lower it holds only token0, at or
+// above tick_upper only token1. Checks the bounds like Position.checkTicks.
+pub fn exit_ticks(tick_lower: i32, tick_upper: i32) -> Result<(i32, i32), UniswapV3MathError> {
+    if tick_lower >= tick_upper {
+        return Err(UniswapV3MathError::Tlu);
+    }
+    if tick_lower < MIN_TICK {
+        return Err(UniswapV3MathError::Tlm);
+    }
+    if tick_upper > MAX_TICK {
+        return Err(UniswapV3MathError::Tum);
+    }
+    Ok((tick_lower, tick_upper))
+}
+
+// Days of fee income a position needs to make up for expected_il, with fees accruing linearly at
+// daily_fee_rate. Both rates are fractions of the position value. Returns f64::INFINITY if that takes
+// longer than holding_days, i.e. the position does not break even while it is held.
+pub fn breakeven_time(daily_fee_rate: f64, expected_il: f64, holding_days: f64) -> f64 {
+    if expected_il <= 0f64 {
+        return 0f64;
+    }
+    if daily_fee_rate <= 0f64 {
+        return f64::INFINITY;
+    }
+
+    let days = expected_il / daily_fee_rate;
+    if days > holding_days {
+        f64::INFINITY
+    } else {
+        days
+    }
+}
+
+// The fees a position earns from volume_in_range, the input swapped while its range was active, as
+// its share position_liquidity / total_active_liquidity of the fee_tier (hundredths of a bip) taken.
+// Rounded down. Zero without active liquidity.
+pub fn expected_fees(
+    volume_in_range: U256,
+    position_liquidity: u128,
+    total_active_liquidity: u128,
+    fee_tier: u32,
+) -> U256 {
+    if total_active_liquidity == 0 {
+        return U256::zero();
+    }
+    let position_liquidity = position_liquidity.min(total_active_liquidity);
+    let fees = volume_in_range.full_mul(U256::from(fee_tier)) * U512::from(position_liquidity)
+        / (U512::from(total_active_liquidity) * U512::from(1000000));
+    U256::try_from(fees).unwrap_or(U256::MAX)
+}
+
+// Fees a position earns over a series of (volume, tick) swaps, counting expected_fees() for each swap
+// whose tick is within [position_lower, position_upper). total_liquidity_fn gives the active
+// liquidity at a tick, the position included.
+pub fn simulate_range_fees<F>(
+    position_lower: i32,
+    position_upper: i32,
+    position_liquidity: u128,
+    swaps: &[(U256, i32)],
+    fee_tier: u32,
+    total_liquidity_fn: F,
+) -> U256
+where
+    F: Fn(i32) -> u128,
+{
+    swaps
+        .iter()
+        .filter(|(_, tick)| position_lower <= *tick && *tick < position_upper)
+        .fold(U256::zero(), |fees, &(volume, tick)| {
+            fees.saturating_add(expected_fees(
+                volume,
+                position_liquidity,
+                total_liquidity_fn(tick),
+                fee_tier,
+            ))
+        })
+}
+
+// returns (bool zeroForOne, uint256 amountIn)
+// The swap that turns held_0 : held_1 into target_ratio, a target_amount_0 : target_amount_1 ratio as
+// returned by mint_amounts(). Selling x of token0 at price p (token1 per token0) net of the fee
+// (hundredths of a bip) solves (held_0 - x) * target_1 = target_0 * (held_1 + x * (1 - fee) * p), and
+// selling token1 the mirrored equation. Assumes the swap does not move the price, so it is a float
+// approximation for swaps small against the pool. Zero when already on target or without a target.
+pub fn rebalance_swap(
+    held_0: U256,
+    held_1: U256,
+    target_ratio: (U256, U256),
+    sqrt_price_x_96: U256,
+    fee: u32,
+) -> (bool, U256) {
+    let (target_0, target_1) = (u256_to_f64(target_ratio.0), u256_to_f64(target_ratio.1));
+    if (target_0 == 0f64 && target_1 == 0f64) || sqrt_price_x_96.is_zero() {
+        return (true, U256::zero());
+    }
+    let (held_0_f, held_1_f) = (u256_to_f64(held_0), u256_to_f64(held_1));
+    let sqrt_price = u256_to_f64(sqrt_price_x_96) / u256_to_f64(Q96);
+    let price = sqrt_price * sqrt_price;
+    let fee_factor = 1f64 - fee as f64 / 1e6;
+
+    let excess = held_0_f * target_1 - target_0 * held_1_f;
+    if excess > 0f64 {
+        let amount = excess / (target_1 + target_0 * fee_factor * price);
+        (true, f64_to_u256(amount).min(held_0))
+    } else {
+        let amount = -excess / (target_0 + target_1 * fee_factor / price);
+        (false, f64_to_u256(amount).min(held_1))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use ethers::types::{U256, U512};
+
+    use crate::{
+        error::UniswapV3MathError,
+        sqrt_price_math::{
+            _get_amount_0_delta, _get_amount_1_delta, get_next_sqrt_price_from_input, Q96,
+        },
+        tick_math::{get_sqrt_ratio_at_tick, MAX_TICK, MIN_TICK},
+        utils::{tick_to_price, u256_to_f64},
+    };
+
+    use super::{
+        amounts_for_budget, breakeven_time, exit_ticks, expected_fees, liquidity_for_swap_impact,
+        mint_amounts, one_sided_amount, optimal_range, range_status, rebalance_swap,
+        simulate_range_fees, sqrt_price_for_ratio, RangeStatus,
+    };
+
+    #[test]
+    fn test_sqrt_price_for_ratio() {
+        let sqrt_ratio_a = get_sqrt_ratio_at_tick(-600).unwrap();
+        let sqrt_ratio_b = get_sqrt_ratio_at_tick(600).unwrap();
+
+        //recovers the price the target amounts were taken at
+        let amount_0 = _get_amount_0_delta(Q96, sqrt_ratio_b, 1e18 as u128, true).unwrap();
+        let amount_1 = _get_amount_1_delta(sqrt_ratio_a, Q96, 1e18 as u128, true).unwrap();
+
+        let result = sqrt_price_for_ratio(sqrt_ratio_a, sqrt_ratio_b, amount_0, amount_1).unwrap();
+        let error = if result > Q96 {
+            result - Q96
+        } else {
+            Q96 - result
+        };
+        assert!(error < Q96 >> 40);
+
+        //bounds are accepted in either order
+        let reversed =
+            sqrt_price_for_ratio(sqrt_ratio_b, sqrt_ratio_a, amount_0, amount_1).unwrap();
+        assert_eq!(reversed, result);
+
+        //single sided targets resolve to the range bounds
+        let result =
+            sqrt_price_for_ratio(sqrt_ratio_a, sqrt_ratio_b, U256::zero(), amount_1).unwrap();
+        assert_eq!(result, sqrt_ratio_b);
+
+        let result =
+            sqrt_price_for_ratio(sqrt_ratio_a, sqrt_ratio_b, amount_0, U256::zero()).unwrap();
+        assert_eq!(result, sqrt_ratio_a);
+    }
+
+    #[test]
+    fn test_one_sided_amount() {
+        let sqrt_ratio_a = get_sqrt_ratio_at_tick(-600).unwrap();
+        let sqrt_ratio_b = get_sqrt_ratio_at_tick(600).unwrap();
+        let liquidity = 1e18 as u128;
+
+        //below the range only token0 is needed
+        let amount_0 = _get_amount_0_delta(sqrt_ratio_a, sqrt_ratio_b, liquidity, true).unwrap();
+        for tick in [-6000, -600] {
+            let sqrt_price = get_sqrt_ratio_at_tick(tick).unwrap();
+            assert_eq!(
+                one_sided_amount(sqrt_price, sqrt_ratio_a, sqrt_ratio_b, liquidity).unwrap(),
+                (true, amount_0)
+            );
+        }
+        //a range symmetric around tick 0 needs the same amount of either token
+        assert_eq!(amount_0, U256::from_dec_str("60005999255049927").unwrap());
+
+        //above the range only token1 is needed
+        let amount_1 = _get_amount_1_delta(sqrt_ratio_a, sqrt_ratio_b, liquidity, true).unwrap();
+        for tick in [600, 6000] {
+            let sqrt_price = get_sqrt_ratio_at_tick(tick).unwrap();
+            assert_eq!(
+                one_sided_amount(sqrt_price, sqrt_ratio_b, sqrt_ratio_a, liquidity).unwrap(),
+                (false, amount_1)
+            );
+        }
+        assert_eq!(amount_1, amount_0);
+
+        //in range deposits need both tokens
+        assert!(one_sided_amount(Q96, sqrt_ratio_a, sqrt_ratio_b, liquidity).is_err());
+    }
+
+    #[test]
+    fn test_breakeven_time() {
+        //0.1% a day in fees against 2% impermanent loss
+        assert!((breakeven_time(0.001, 0.02, 30f64) - 20f64).abs() < 1e-9);
+        assert!((breakeven_time(0.0005, 0.0125, 365f64) - 25f64).abs() < 1e-9);
+
+        //not within the holding period
+        assert_eq!(breakeven_time(0.001, 0.02, 10f64), f64::INFINITY);
+        //no fees never make up for a loss, no loss needs no fees
+        assert_eq!(breakeven_time(0f64, 0.02, 365f64), f64::INFINITY);
+        assert_eq!(breakeven_time(0f64, 0f64, 365f64), 0f64);
+    }
+
+    #[test]
+    fn test_exit_ticks() {
+        assert_eq!(exit_ticks(-600, 600).unwrap(), (-600, 600));
+        assert_eq!(
+            exit_ticks(MIN_TICK, MAX_TICK).unwrap(),
+            (MIN_TICK, MAX_TICK)
+        );
+
+        //the position is single sided at the returned ticks
+        let (tick_token_0, tick_token_1) = exit_ticks(-600, 600).unwrap();
+        let sqrt_ratio_a = get_sqrt_ratio_at_tick(-600).unwrap();
+        let sqrt_ratio_b = get_sqrt_ratio_at_tick(600).unwrap();
+        for (tick, is_token_0) in [(tick_token_0, true), (tick_token_1, false)] {
+            let sqrt_price = get_sqrt_ratio_at_tick(tick).unwrap();
+            let (token, _) =
+                one_sided_amount(sqrt_price, sqrt_ratio_a, sqrt_ratio_b, 1e18 as u128).unwrap();
+            assert_eq!(token, is_token_0);
+        }
+
+        assert!(matches!(exit_ticks(600, 600), Err(UniswapV3MathError::Tlu)));
+        assert!(matches!(
+            exit_ticks(600, -600),
+            Err(UniswapV3MathError::Tlu)
+        ));
+        assert!(matches!(
+            exit_ticks(MIN_TICK - 1, 0),
+            Err(UniswapV3MathError::Tlm)
+        ));
+        assert!(matches!(
+            exit_ticks(0, MAX_TICK + 1),
+            Err(UniswapV3MathError::Tum)
+        ));
+    }
+
+    #[test]
+    fn test_expected_fees() {
+        let volume = U256::from(10).pow(U256::from(24));
+
+        //a quarter of the liquidity earns a quarter of the 0.3% fee
+        assert_eq!(
+            expected_fees(volume, 25, 100, 3000),
+            U256::from(75) * U256::from(10).pow(U256::from(19))
+        );
+        //all of it
+        assert_eq!(
+            expected_fees(volume, 100, 100, 500),
+            U256::from(5) * U256::from(10).pow(U256::from(20))
+        );
+        //rounded down
+        assert_eq!(expected_fees(U256::from(999), 1, 3, 10000), U256::from(3));
+
+        //no active liquidity or volume
+        assert_eq!(expected_fees(volume, 0, 0, 3000), U256::zero());
+        assert_eq!(expected_fees(U256::zero(), 25, 100, 3000), U256::zero());
+        assert_eq!(expected_fees(volume, 0, 100, 3000), U256::zero());
+
+        //does not overflow at the extremes
+        assert_eq!(
+            expected_fees(U256::MAX, u128::MAX, u128::MAX, 1000000),
+            U256::MAX
+        );
+    }
+
+    #[test]
+    fn test_range_status() {
+        let sqrt_ratio_a = get_sqrt_ratio_at_tick(-600).unwrap();
+        let sqrt_ratio_b = get_sqrt_ratio_at_tick(600).unwrap();
+        let status = |tick: i32| {
+            range_status(
+                get_sqrt_ratio_at_tick(tick).unwrap(),
+                sqrt_ratio_a,
+                sqrt_ratio_b,
+            )
+            .unwrap()
+        };
+
+        assert_eq!(status(0), RangeStatus::InRange);
+        assert_eq!(status(-600), RangeStatus::InRange);
+        assert_eq!(status(599), RangeStatus::InRange);
+
+        assert_eq!(status(-601), RangeStatus::BelowRange(1));
+        assert_eq!(status(-1000), RangeStatus::BelowRange(400));
+        //just below the lower bound is still a tick away
+        assert_eq!(
+            range_status(sqrt_ratio_a - 1, sqrt_ratio_a, sqrt_ratio_b).unwrap(),
+            RangeStatus::BelowRange(1)
+        );
+
+        assert_eq!(status(600), RangeStatus::AboveRange(0));
+        assert_eq!(status(1000), RangeStatus::AboveRange(400));
+
+        //bounds are accepted in either order
+        assert_eq!(
+            range_status(
+                get_sqrt_ratio_at_tick(1000).unwrap(),
+                sqrt_ratio_b,
+                sqrt_ratio_a
+            )
+            .unwrap(),
+            RangeStatus::AboveRange(400)
+        );
+    }
+
+    #[test]
+    fn test_simulate_range_fees() {
+        let volume = U256::from(10).pow(U256::from(24));
+        //the position is half of the liquidity below tick 0 and a quarter above it
+        let total_liquidity = |tick: i32| if tick < 0 { 200 } else { 400 };
+        let swaps = [
+            (volume, -700),
+            (volume, -600),
+            (volume, -1),
+            (volume, 0),
+            (volume, 599),
+            (volume, 600),
+        ];
+
+        let fees = simulate_range_fees(-600, 600, 100, &swaps, 3000, total_liquidity);
+        //1.5e21 for each of the two swaps below 0, 7.5e20 for each of the two above
+        assert_eq!(fees, U256::from(45) * U256::from(10).pow(U256::from(20)));
+        assert_eq!(
+            fees,
+            swaps[1..5]
+                .iter()
+                .map(|&(volume, tick)| expected_fees(volume, 100, total_liquidity(tick), 3000))
+                .fold(U256::zero(), |a, b| a + b)
+        );
+
+        //no swap within the range
+        assert_eq!(
+            simulate_range_fees(1000, 2000, 100, &swaps, 3000, total_liquidity),
+            U256::zero()
+        );
+    }
+
+    #[test]
+    fn test_optimal_range() {
+        //ticks -512.9 and 769.6, widened to multiples of 60
+        let (tick_lower, tick_upper) = optimal_range(1f64, 0.95, 1.08, 18, 18, 60).unwrap();
+        assert_eq!((tick_lower, tick_upper), (-540, 780));
+        assert!(tick_to_price(tick_lower, 18, 18) <= 0.95);
+        assert!(tick_to_price(tick_upper, 18, 18) >= 1.08);
+
+        //the same raw prices for a token0 of 6 decimals
+        let (tick_lower, tick_upper) = optimal_range(1e-12, 0.95e-12, 1.08e-12, 6, 18, 60).unwrap();
+        assert_eq!((tick_lower, tick_upper), (-540, 780));
+        assert!(tick_to_price(tick_lower, 6, 18) <= 0.95e-12);
+        assert!(tick_to_price(tick_upper, 6, 18) >= 1.08e-12);
+
+        //widened to the current price if the forecast is away from it, in either order
+        assert_eq!(
+            optimal_range(1f64, 1.08, 1.02, 18, 18, 60).unwrap(),
+            (0, 780)
+        );
+
+        //a forecast within one tick still spans a spacing
+        let (tick_lower, tick_upper) = optimal_range(1f64, 1f64, 1f64, 18, 18, 10).unwrap();
+        assert_eq!((tick_lower, tick_upper), (0, 10));
+
+        //kept within the usable ticks
+        assert_eq!(
+            optimal_range(1f64, 1e-60, 1e60, 18, 18, 60).unwrap(),
+            ((MIN_TICK / 60) * 60, (MAX_TICK / 60) * 60)
+        );
+
+        //no tick spacing to round to
+        assert!(matches!(
+            optimal_range(1f64, 0.95, 1.08, 18, 18, 0),
+            Err(UniswapV3MathError::InvalidTickSpacing(0))
+        ));
+        //prices without a tick
+        for price in [0f64, -1f64, f64::NAN, f64::INFINITY] {
+            assert!(matches!(
+                optimal_range(1f64, price, 1.08, 18, 18, 60),
+                Err(UniswapV3MathError::InvalidPrice(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_liquidity_for_swap_impact() {
+        let amount = U256::from(10).pow(U256::from(18));
+        let sqrt_price = get_sqrt_ratio_at_tick(0).unwrap();
+        assert_eq!(
+            liquidity_for_swap_impact(amount, 50, sqrt_price, true),
+            398499373432600033317
+        );
+        assert_eq!(
+            liquidity_for_swap_impact(amount, 50, sqrt_price, false),
+            400499376557634213508
+        );
+
+        //price move in bps, exact
+        let within = |liquidity: u128, max_impact_bps: u32, sqrt_price: U256, zero_for_one| {
+            let next = get_next_sqrt_price_from_input(sqrt_price, liquidity, amount, zero_for_one)
+                .unwrap();
+            let before = sqrt_price.full_mul(sqrt_price) * U512::from(10000);
+            let after = next.full_mul(next) * U512::from(10000);
+            let allowed = sqrt_price.full_mul(sqrt_price) * U512::from(max_impact_bps);
+            if zero_for_one {
+                before - after <= allowed
+            } else {
+                after - before <= allowed
+            }
+        };
+        for tick in [-50000, 0, 80000] {
+            let sqrt_price = get_sqrt_ratio_at_tick(tick).unwrap();
+            for zero_for_one in [true, false] {
+                for max_impact_bps in [1, 50, 1000] {
+                    let liquidity =
+                        liquidity_for_swap_impact(amount, max_impact_bps, sqrt_price, zero_for_one);
+                    assert!(within(liquidity, max_impact_bps, sqrt_price, zero_for_one));
+                    //0.1% less liquidity moves the price too far
+                    assert!(!within(
+                        liquidity / 1000 * 999,
+                        max_impact_bps,
+                        sqrt_price,
+                        zero_for_one
+                    ));
+                }
+            }
+        }
+
+        assert_eq!(
+            liquidity_for_swap_impact(U256::zero(), 50, sqrt_price, true),
+            0
+        );
+        assert_eq!(
+            liquidity_for_swap_impact(amount, 0, sqrt_price, true),
+            u128::MAX
+        );
+        assert_eq!(
+            liquidity_for_swap_impact(amount, 10000, sqrt_price, true),
+            0
+        );
+    }
+
+    #[test]
+    fn test_mint_amounts() {
+        //the mints of UniswapV3Pool.spec on a pool initialized at a price of 1:10 with tick spacing 60
+        let sqrt_price = U256::from_dec_str("25054144837504793118641380156").unwrap();
+        let (min_tick, max_tick) = (-887220, 887220);
+
+        //price within range
+        assert_eq!(
+            mint_amounts(sqrt_price, min_tick + 60, max_tick - 60, 100).unwrap(),
+            (U256::from(317), U256::from(32))
+        );
+        //range below the current price, token1 only
+        assert_eq!(
+            mint_amounts(sqrt_price, -46080, -23040, 10000).unwrap(),
+            (U256::zero(), U256::from(2162))
+        );
+        //range above the current price, token0 only
+        assert_eq!(
+            mint_amounts(sqrt_price, -22980, 0, 10000).unwrap(),
+            (U256::from(21549), U256::zero())
+        );
+
+        assert!(matches!(
+            mint_amounts(sqrt_price, 0, 0, 100),
+            Err(UniswapV3MathError::Tlu)
+        ));
+    }
+
+    #[test]
+    fn test_rebalance_swap() {
+        let e18 = U256::exp10(18);
+
+        //price 1, all token0 to half and half: 100 - x = 0.997 * x
+        let (zero_for_one, amount) = rebalance_swap(
+            U256::from(100) * e18,
+            U256::zero(),
+            (U256::one(), U256::one()),
+            Q96,
+            3000,
+        );
+        assert!(zero_for_one);
+        let remaining = u256_to_f64(U256::from(100) * e18 - amount);
+        let received = u256_to_f64(amount) * 0.997;
+        assert!((remaining / received - 1f64).abs() < 1e-12);
+
+        //price 4, all token1 to the 1 : 4 ratio of a position centered on the price
+        let sqrt_price = Q96 * 2;
+        let (zero_for_one, amount) = rebalance_swap(
+            U256::zero(),
+            U256::from(100) * e18,
+            (U256::one(), U256::from(4)),
+            sqrt_price,
+            3000,
+        );
+        assert!(!zero_for_one);
+        let received = u256_to_f64(amount) * 0.997 / 4f64;
+        let remaining = u256_to_f64(U256::from(100) * e18 - amount);
+        assert!((remaining / (received * 4f64) - 1f64).abs() < 1e-12);
+
+        //a target from mint_amounts is met by the holdings it returns
+        let sqrt_price = get_sqrt_ratio_at_tick(1000).unwrap();
+        let (amount_0, amount_1) = mint_amounts(sqrt_price, 0, 2000, 1e18 as u128).unwrap();
+        let (_, amount) =
+            rebalance_swap(amount_0, amount_1, (amount_0, amount_1), sqrt_price, 3000);
+        assert_eq!(amount, U256::zero());
+
+        //a single sided target sells everything of the other token
+        assert_eq!(
+            rebalance_swap(e18, e18, (U256::zero(), U256::one()), Q96, 500),
+            (true, e18)
+        );
+        assert_eq!(
+            rebalance_swap(e18, e18, (U256::one(), U256::zero()), Q96, 500),
+            (false, e18)
+        );
+
+        //no target
+        assert_eq!(
+            rebalance_swap(e18, e18, (U256::zero(), U256::zero()), Q96, 500),
+            (true, U256::zero())
+        );
+    }
+
+    #[test]
+    fn test_amounts_for_budget() {
+        let e18 = U256::exp10(18);
+        let sqrt_price = get_sqrt_ratio_at_tick(0).unwrap();
+
+        //in range at price 1, the half as large token1 budget decides
+        let (budget_0, budget_1) = (e18, e18 / 2);
+        let (amount_0, amount_1, liquidity) =
+            amounts_for_budget(budget_0, budget_1, sqrt_price, -600, 600).unwrap();
+        assert_eq!(
+            (amount_0, amount_1),
+            mint_amounts(sqrt_price, -600, 600, liquidity).unwrap()
+        );
+        //a symmetric range needs as much of both at price 1
+        assert_eq!(liquidity, 16918749904869185713);
+        assert_eq!((amount_0, amount_1), (budget_1, budget_1));
+        //and one more wei of liquidity is over budget
+        let (more_0, more_1) = mint_amounts(sqrt_price, -600, 600, liquidity + 1).unwrap();
+        assert!(more_0 > budget_0 || more_1 > budget_1);
+
+        //below the range only token0 is used, all of it but for rounding
+        let (amount_0, amount_1, liquidity) =
+            amounts_for_budget(budget_0, budget_1, sqrt_price, 600, 1200).unwrap();
+        assert_eq!(amount_1, U256::zero());
+        assert!(liquidity > 0 && budget_0 - amount_0 <= U256::from(2));
+
+        //above the range only token1
+        let (amount_0, amount_1, _) =
+            amounts_for_budget(budget_0, budget_1, sqrt_price, -1200, -600).unwrap();
+        assert_eq!(amount_0, U256::zero());
+        assert!(budget_1 - amount_1 <= U256::from(2));
+
+        //without the token the range needs nothing can be minted
+        assert_eq!(
+            amounts_for_budget(U256::zero(), budget_1, sqrt_price, -600, 600).unwrap(),
+            (U256::zero(), U256::zero(), 0)
+        );
+    }
+}
@@ -41,14 +41,23 @@ mod test {
 
         // 2**128-15 + 15 overflows
         let result = add_delta(340282366920938463463374607431768211441, 15);
-        assert_eq!(result.err().unwrap().to_string(), "Liquidity Add");
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "Liquidity overflows when adding liquidity"
+        );
 
         // 0 + -1 underflows
         let result = add_delta(0, -1);
-        assert_eq!(result.err().unwrap().to_string(), "Liquidity Sub");
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "Liquidity underflows when removing liquidity"
+        );
 
         // 3 + -4 underflows
         let result = add_delta(3, -4);
-        assert_eq!(result.err().unwrap().to_string(), "Liquidity Sub");
+        assert_eq!(
+            result.err().unwrap().to_string(),
+            "Liquidity underflows when removing liquidity"
+        );
     }
 }
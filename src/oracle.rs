@@ -0,0 +1,41 @@
+use crate::utils::tick_to_price;
+
+// returns (min_price, max_price)
+// The lowest and highest price of token0 in token1, adjusted for the decimals, among observed ticks,
+// e.g. the ticks of Swap logs or ticks derived from oracle observations. The price grows with the tick,
+// so these are the prices of the lowest and highest tick. None without observations.
+pub fn price_range_from_ticks(ticks: &[i32], decimals_0: u8, decimals_1: u8) -> Option<(f64, f64)> {
+    let min_tick = *ticks.iter().min()?;
+    let max_tick = *ticks.iter().max()?;
+    Some((
+        tick_to_price(min_tick, decimals_0, decimals_1),
+        tick_to_price(max_tick, decimals_0, decimals_1),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::utils::tick_to_price;
+
+    use super::price_range_from_ticks;
+
+    #[test]
+    fn test_price_range_from_ticks() {
+        let (min_price, max_price) =
+            price_range_from_ticks(&[120, -300, 0, 6932, -60], 18, 18).unwrap();
+        assert_eq!(min_price, tick_to_price(-300, 18, 18));
+        assert_eq!(max_price, tick_to_price(6932, 18, 18));
+        assert!((max_price - 2f64).abs() < 1e-3);
+
+        //18 decimals token0 against 6 decimals token1, around 2000 token1 per token0
+        let (min_price, max_price) = price_range_from_ticks(&[-200311, -200000], 18, 6).unwrap();
+        assert!(min_price < max_price);
+        assert!((min_price / 2000f64 - 1f64).abs() < 1e-3);
+
+        //a single observation
+        let (min_price, max_price) = price_range_from_ticks(&[0], 18, 18).unwrap();
+        assert_eq!((min_price, max_price), (1f64, 1f64));
+
+        assert!(price_range_from_ticks(&[], 18, 18).is_none());
+    }
+}
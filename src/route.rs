@@ -0,0 +1,159 @@
+use crate::error::UniswapV3MathError;
+use crate::swap::{default_sqrt_price_limit, PoolState, SwapResult};
+use crate::utils::u256_to_f64;
+use ethers::prelude::{I256, U256};
+
+// A pool of a route and the direction it is swapped in
+#[derive(Clone)]
+pub struct Hop {
+    pub pool: PoolState,
+    pub zero_for_one: bool,
+    pub fee: u32,
+}
+
+// The swap of one hop and the sqrt price of its pool before it
+#[derive(Clone, Debug)]
+pub struct HopResult {
+    pub zero_for_one: bool,
+    pub sqrt_price_before: U256,
+    pub result: SwapResult,
+}
+
+#[derive(Clone, Debug)]
+pub struct RouteResult {
+    pub amount_in: U256,
+    pub amount_out: U256,
+    pub hops: Vec<HopResult>,
+}
+
+impl RouteResult {
+    // How much less the route paid out than the product of the hops' spot prices, in bps, fees
+    // included. Both amounts are raw, so the decimals of the tokens do not matter.
+    pub fn total_slippage_bps(&self) -> i32 {
+        if self.amount_in.is_zero() {
+            return 0;
+        }
+        let spot_rate = self
+            .hops
+            .iter()
+            .map(|hop| spot_rate(hop.sqrt_price_before, hop.zero_for_one))
+            .product::<f64>();
+        let realized_rate = u256_to_f64(self.amount_out) / u256_to_f64(self.amount_in);
+        ((1f64 - realized_rate / spot_rate) * 10000f64).round() as i32
+    }
+
+    // How far each hop moved the price of its pool, in bps of the price before it. The largest is the
+    // bottleneck of the route.
+    pub fn per_hop_impact(&self) -> Vec<i32> {
+        self.hops
+            .iter()
+            .map(|hop| {
+                let ratio =
+                    u256_to_f64(hop.result.sqrt_price_after) / u256_to_f64(hop.sqrt_price_before);
+                ((ratio * ratio - 1f64).abs() * 10000f64).round() as i32
+            })
+            .collect()
+    }
+}
+
+// Raw output token per input token at a sqrt price
+fn spot_rate(sqrt_price_x96: U256, zero_for_one: bool) -> f64 {
+    let sqrt_price = u256_to_f64(sqrt_price_x96) / 2f64.powi(96);
+    let price = sqrt_price * sqrt_price;
+    if zero_for_one {
+        price
+    } else {
+        1f64 / price
+    }
+}
+
+// Exact input swap through the hops in order, each hop swapping the output of the one before it at the
+// default price limits
+pub fn swap_route(hops: &[Hop], amount_in: U256) -> Result<RouteResult, UniswapV3MathError> {
+    let mut amount = amount_in;
+    let mut hop_results = Vec::with_capacity(hops.len());
+    for hop in hops {
+        let result = hop.pool.swap(
+            hop.zero_for_one,
+            I256::from_raw(amount),
+            default_sqrt_price_limit(hop.zero_for_one),
+            hop.fee,
+        )?;
+        amount = result.io(hop.zero_for_one).1;
+        hop_results.push(HopResult {
+            zero_for_one: hop.zero_for_one,
+            sqrt_price_before: hop.pool.slot0.sqrt_price,
+            result,
+        });
+    }
+    Ok(RouteResult {
+        amount_in,
+        amount_out: amount,
+        hops: hop_results,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use ethers::types::U256;
+
+    use crate::swap::test::{pool_with_positions, test_pool};
+
+    use super::{swap_route, Hop};
+
+    fn two_hops() -> Vec<Hop> {
+        vec![
+            Hop {
+                pool: test_pool(),
+                zero_for_one: true,
+                fee: 3000,
+            },
+            //sells the token1 of the first hop
+            Hop {
+                pool: pool_with_positions(&[(-600, 600, 2e18 as u128)], 60, 0),
+                zero_for_one: false,
+                fee: 500,
+            },
+        ]
+    }
+
+    #[test]
+    fn test_swap_route() {
+        let route = swap_route(&two_hops(), U256::from(10000000000000000u64)).unwrap();
+        assert_eq!(route.hops.len(), 2);
+        assert_eq!(
+            route.hops[0].result.amount1_delta.unsigned_abs(),
+            U256::from(9871580343970612u64)
+        );
+        assert_eq!(
+            route.hops[1].result.amount1_delta.unsigned_abs(),
+            U256::from(9871580343970612u64)
+        );
+        assert_eq!(route.amount_out, U256::from(9818208168720641u64));
+    }
+
+    #[test]
+    fn test_total_slippage_bps() {
+        //both pools start at price 1, so the route loses 1 - 9818208168720641 / 1e16
+        let route = swap_route(&two_hops(), U256::from(10000000000000000u64)).unwrap();
+        assert_eq!(route.total_slippage_bps(), 182);
+
+        //a tiny amount only pays the fees, 1 - 0.997 * 0.9995
+        let route = swap_route(&two_hops(), U256::from(1000000000u64)).unwrap();
+        assert_eq!(route.total_slippage_bps(), 35);
+    }
+
+    #[test]
+    fn test_per_hop_impact() {
+        //the second pool is twice as deep, and is swapped half as far
+        let route = swap_route(&two_hops(), U256::from(10000000000000000u64)).unwrap();
+        assert_eq!(route.per_hop_impact(), vec![196, 99]);
+
+        let route = swap_route(&two_hops()[1..], U256::from(10000000000000000u64)).unwrap();
+        assert_eq!(route.per_hop_impact(), vec![100]);
+
+        //nothing swapped
+        let route = swap_route(&two_hops(), U256::zero()).unwrap();
+        assert_eq!(route.per_hop_impact(), vec![0, 0]);
+    }
+}
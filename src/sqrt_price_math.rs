@@ -1,13 +1,15 @@
 use crate::{
     error::UniswapV3MathError,
     full_math::{mul_div, mul_div_rounding_up},
+    tick_math::get_sqrt_ratio_at_tick,
     unsafe_math::div_rounding_up,
-    utils::{ruint_to_u256, u256_to_ruint},
+    utils::{ruint_to_u256, sqrt_u256, u256_to_ruint},
 };
 use ethers::types::{I256, U256};
 
 pub const MAX_U160: U256 = U256([18446744073709551615, 18446744073709551615, 4294967295, 0]);
 pub const Q96: U256 = U256([0, 4294967296, 0, 0]);
+pub const Q128: U256 = U256([0, 0, 1, 0]);
 pub const FIXED_POINT_96_RESOLUTION: U256 = U256([96, 0, 0, 0]);
 
 // returns (sqrtQX96)
@@ -233,15 +235,52 @@ pub fn get_amount_1_delta(
     }
 }
 
+// returns (uint160 sqrtQX96)
+// sqrt(sqrt_ratio_a_x_96 * sqrt_ratio_b_x_96), the sqrt price of the geometric mean of both prices
+pub fn geometric_mean_sqrt_price(
+    sqrt_ratio_a_x_96: U256,
+    sqrt_ratio_b_x_96: U256,
+) -> Result<U256, UniswapV3MathError> {
+    let (product, overflow) = sqrt_ratio_a_x_96.overflowing_mul(sqrt_ratio_b_x_96);
+    if !overflow {
+        return Ok(sqrt_u256(product));
+    }
+
+    // sqrt(a * b) = sqrt(a * b / 2**64) * 2**32, exact up to the 32 low bits
+    Ok(sqrt_u256(mul_div(
+        sqrt_ratio_a_x_96,
+        sqrt_ratio_b_x_96,
+        U256::one() << 64,
+    )?) << 32)
+}
+
+// returns (uint256 reserve0, uint256 reserve1)
+// The virtual reserves of liquidity at the price of tick, the amounts of a constant product pool
+// x * y = L^2 at that price: x = L / sqrt(P) and y = L * sqrt(P), rounded down
+pub fn reserves_at_tick(tick: i32, liquidity: u128) -> Result<(U256, U256), UniswapV3MathError> {
+    let sqrt_price = get_sqrt_ratio_at_tick(tick)?;
+    let liquidity = U256::from(liquidity);
+    Ok((
+        mul_div(liquidity, Q96, sqrt_price)?,
+        mul_div(liquidity, sqrt_price, Q96)?,
+    ))
+}
+
 #[cfg(test)]
 mod test {
     use std::ops::{Add, Sub};
 
     use ethers::types::U256;
 
-    use crate::sqrt_price_math::{_get_amount_1_delta, get_next_sqrt_price_from_output, MAX_U160};
+    use crate::{
+        sqrt_price_math::{_get_amount_1_delta, get_next_sqrt_price_from_output, MAX_U160},
+        tick_math::{get_sqrt_ratio_at_tick, MAX_SQRT_RATIO, MAX_TICK},
+    };
 
-    use super::{_get_amount_0_delta, get_next_sqrt_price_from_input};
+    use super::{
+        _get_amount_0_delta, geometric_mean_sqrt_price, get_next_sqrt_price_from_input,
+        reserves_at_tick,
+    };
 
     #[test]
     fn test_get_next_sqrt_price_from_input() {
@@ -389,7 +428,7 @@ mod test {
         );
         assert_eq!(
             result.unwrap_err().to_string(),
-            "require((product = amount * sqrtPX96) / amount == sqrtPX96 && numerator1 > product);"
+            "Product of amount and sqrt price overflows or is not less than liquidity << 96"
         );
 
         //fails if output amount is greater than virtual reserves of token0
@@ -401,7 +440,7 @@ mod test {
         );
         assert_eq!(
             result.unwrap_err().to_string(),
-            "require((product = amount * sqrtPX96) / amount == sqrtPX96 && numerator1 > product);"
+            "Product of amount and sqrt price overflows or is not less than liquidity << 96"
         );
 
         //fails if output amount is greater than virtual reserves of token1
@@ -449,7 +488,7 @@ mod test {
         );
         assert_eq!(
             result.unwrap_err().to_string(),
-            "require((product = amount * sqrtPX96) / amount == sqrtPX96 && numerator1 > product);"
+            "Product of amount and sqrt price overflows or is not less than liquidity << 96"
         );
 
         //returns input price if amount in is zero and zeroForOne = true
@@ -521,7 +560,7 @@ mod test {
         );
         assert_eq!(
             result.unwrap_err().to_string(),
-            "require((product = amount * sqrtPX96) / amount == sqrtPX96 && numerator1 > product);"
+            "Product of amount and sqrt price overflows or is not less than liquidity << 96"
         );
     }
 
@@ -656,4 +695,51 @@ mod test {
 
         assert_eq!(amount_0_delta, U256::from(406));
     }
+
+    #[test]
+    fn test_geometric_mean_sqrt_price() {
+        //product fits in 256 bits, the result is the floor of the square root
+        let a = get_sqrt_ratio_at_tick(0).unwrap();
+        let b = get_sqrt_ratio_at_tick(50000).unwrap();
+        let mean = geometric_mean_sqrt_price(a, b).unwrap();
+        assert!(mean.full_mul(mean) <= a.full_mul(b));
+        assert!((mean + 1).full_mul(mean + 1) > a.full_mul(b));
+        assert_eq!(geometric_mean_sqrt_price(b, a).unwrap(), mean);
+
+        //product overflows 256 bits, only the low 32 bits are lost
+        let a = MAX_SQRT_RATIO - 1;
+        let b = get_sqrt_ratio_at_tick(800000).unwrap();
+        let mean = geometric_mean_sqrt_price(a, b).unwrap();
+        assert!(mean.full_mul(mean) <= a.full_mul(b));
+        let upper = mean + (U256::one() << 32);
+        assert!(upper.full_mul(upper) > a.full_mul(b));
+
+        //the mean of a price with itself is the price
+        let c = get_sqrt_ratio_at_tick(-12345).unwrap();
+        assert_eq!(geometric_mean_sqrt_price(c, c).unwrap(), c);
+    }
+
+    #[test]
+    fn test_reserves_at_tick() {
+        let liquidity = 1000000000000000000u128;
+        assert_eq!(
+            reserves_at_tick(0, liquidity).unwrap(),
+            (U256::from(liquidity), U256::from(liquidity))
+        );
+
+        //about twice the price, the reserves move by sqrt(2) each way and mirror at the negated tick
+        let (reserve_0, reserve_1) = reserves_at_tick(6932, liquidity).unwrap();
+        assert_eq!(reserve_0, U256::from(707100360067216880u128));
+        assert_eq!(reserve_1, U256::from(1414226404728375633u128));
+        assert_eq!(
+            reserves_at_tick(-6932, liquidity).unwrap(),
+            (reserve_1, reserve_0)
+        );
+
+        assert_eq!(
+            reserves_at_tick(100, 0).unwrap(),
+            (U256::zero(), U256::zero())
+        );
+        assert!(reserves_at_tick(MAX_TICK + 1, liquidity).is_err());
+    }
 }
@@ -1,10 +1,18 @@
+use crate::abi;
 use crate::error::UniswapV3MathError;
+use crate::f64_swap;
+use crate::full_math;
 use crate::liquidity_math;
+use crate::sqrt_price_math;
 use crate::swap_math;
+use crate::tick;
 use crate::tick_bitmap;
 use crate::tick_math;
+use crate::utils::{
+    f64_to_u256, liquidity_to_f64, price_to_sqrt_price_x96, sqrt_u256, tick_to_price, u256_to_f64,
+};
 use ethers::prelude::*;
-use hashbrown::HashMap;
+use hashbrown::{HashMap, HashSet};
 
 #[derive(Clone)]
 pub struct TickInfo {
@@ -15,27 +23,432 @@ pub struct TickInfo {
     pub l_net: i128,
 }
 
+impl TickInfo {
+    pub fn new(index: i32, l_gross: u128, l_net: i128) -> Self {
+        TickInfo {
+            index,
+            l_gross,
+            l_net,
+        }
+    }
+
+    // For a tick referenced by a single position, whose gross liquidity is the size of its net
+    pub fn from_net(index: i32, l_net: i128) -> Self {
+        TickInfo::new(index, l_net.unsigned_abs(), l_net)
+    }
+}
+
 // 代表pool的当前状况
+#[derive(Clone)]
 pub struct Slot0 {
     pub sqrt_price: U256,
     pub liquidity: u128,
     pub tick: i32,
 }
 
+#[derive(Clone, Debug, Default)]
 pub struct SwapResult {
     pub amount0_delta: I256,
     pub amount1_delta: I256,
     pub sqrt_price_after: U256,
     pub liquidity_after: u128,
     pub tick_after: i32,
+    // total fee paid in the input token, protocol fee included
+    pub fee_amount: U256,
+    // part of fee_amount taken by the protocol, zero unless swapped with swap_with_protocol_fee()
+    pub protocol_fee: U256,
+    // lowest and highest sqrt price the swap went through, start and end price included
+    pub sqrt_price_min: U256,
+    pub sqrt_price_max: U256,
 }
 
-struct SwapState {
-    amount_specified_remaining: I256,
-    amount_calculated: I256,
-    sqrt_price_x96: U256,
-    tick: i32,
-    liquidity: u128,
+impl SwapResult {
+    // Fee paid as a share of the total input, in bps
+    pub fn effective_fee_bps(&self) -> f64 {
+        let amount_in = if self.amount0_delta.is_positive() {
+            self.amount0_delta
+        } else {
+            self.amount1_delta
+        };
+        if !amount_in.is_positive() {
+            return 0f64;
+        }
+        u256_to_f64(self.fee_amount) / u256_to_f64(amount_in.into_raw()) * 10000f64
+    }
+
+    // (amount_in, amount_out) of a swap in the given direction, whether it was exact input or output
+    pub fn io(&self, zero_for_one: bool) -> (U256, U256) {
+        let (amount_in, amount_out) = if zero_for_one {
+            (self.amount0_delta, self.amount1_delta)
+        } else {
+            (self.amount1_delta, self.amount0_delta)
+        };
+        (amount_in.unsigned_abs(), amount_out.unsigned_abs())
+    }
+
+    // returns (int256 amount0, int256 amount1)
+    // What the pool gained (positive) or lost (negative) of each token. Like UniswapV3Pool.swap, the
+    // deltas of a SwapResult are already from the pool's side, so they are returned as is: the
+    // negation of what the trader gained, positive for the input and negative for the output.
+    pub fn pool_balance_change(&self) -> (I256, I256) {
+        (self.amount0_delta, self.amount1_delta)
+    }
+
+    // (lowest, highest) sqrt price touched by the swap
+    pub fn price_range(&self) -> (U256, U256) {
+        (self.sqrt_price_min, self.sqrt_price_max)
+    }
+
+    // How much more output than reference_out the swap realized, in signed bps of reference_out,
+    // truncated towards zero and saturating at the bounds of i32
+    pub fn price_improvement_bps(&self, reference_out: U256) -> i32 {
+        let amount_out = if self.amount0_delta.is_negative() {
+            self.amount0_delta.unsigned_abs()
+        } else if self.amount1_delta.is_negative() {
+            self.amount1_delta.unsigned_abs()
+        } else {
+            U256::zero()
+        };
+        if reference_out.is_zero() {
+            return if amount_out.is_zero() { 0 } else { i32::MAX };
+        }
+
+        let (diff, improved) = if amount_out >= reference_out {
+            (amount_out - reference_out, true)
+        } else {
+            (reference_out - amount_out, false)
+        };
+        let bps = (diff.full_mul(U256::from(10000)) / U512::from(reference_out))
+            .min(U512::from(i32::MAX))
+            .low_u32() as i32;
+        if improved {
+            bps
+        } else {
+            -bps
+        }
+    }
+}
+
+// One iteration of the swap loop: a move from sqrt_price_start_x96 towards the next tick
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SwapStepTrace {
+    pub sqrt_price_start_x96: U256,
+    pub sqrt_price_end_x96: U256,
+    pub tick_start: i32,
+    pub tick_end: i32,
+    // the tick the step swapped towards and whether the bitmap has it initialized
+    pub tick_next: i32,
+    pub initialized: bool,
+    // liquidity the step was computed with, before crossing tick_next
+    pub liquidity: u128,
+    pub amount_in: U256,
+    pub amount_out: U256,
+    pub fee_amount: U256,
+}
+
+// The fractional tick of a sqrt price
+fn fractional_tick(sqrt_price: U256) -> f64 {
+    2f64 * (u256_to_f64(sqrt_price) / 2f64.powi(96)).ln() / 1.0001f64.ln()
+}
+
+// Liquidity weighted average tick at which a traced swap executed. Every step contributes the middle of
+// the tick range it moved through, weighted by its liquidity times the ticks it moved. None if the
+// swap did not move the price.
+pub fn average_execution_tick(trace: &[SwapStepTrace]) -> Option<i32> {
+    let mut weighted_sum = 0f64;
+    let mut total_weight = 0f64;
+    for step in trace {
+        let tick_start = fractional_tick(step.sqrt_price_start_x96);
+        let tick_end = fractional_tick(step.sqrt_price_end_x96);
+        let weight = liquidity_to_f64(step.liquidity) * (tick_end - tick_start).abs();
+        weighted_sum += weight * (tick_start + tick_end) / 2f64;
+        total_weight += weight;
+    }
+
+    if total_weight == 0f64 {
+        return None;
+    }
+    Some((weighted_sum / total_weight).round() as i32)
+}
+
+// Volume weighted average tick of a swap along its price path: the fractional mid tick of every step
+// weighted by the step's amount in, rounded. Unlike average_execution_tick the weight is the amount
+// traded rather than the liquidity times the ticks moved. The current tick if nothing is traded.
+pub fn vwap_tick(
+    pool: &PoolState,
+    zero_for_one: bool,
+    amount_specified: I256,
+    fee: u32,
+) -> Result<i32, UniswapV3MathError> {
+    let (_, trace) = swap_traced(
+        &pool.ticks,
+        &pool.tick_bitmap,
+        pool.tick_spacing,
+        zero_for_one,
+        amount_specified,
+        default_sqrt_price_limit(zero_for_one),
+        &pool.slot0,
+        fee,
+    )?;
+    let mut weighted_sum = 0f64;
+    let mut total_weight = 0f64;
+    for step in &trace {
+        let weight = u256_to_f64(step.amount_in);
+        weighted_sum += weight
+            * (fractional_tick(step.sqrt_price_start_x96)
+                + fractional_tick(step.sqrt_price_end_x96))
+            / 2f64;
+        total_weight += weight;
+    }
+
+    if total_weight == 0f64 {
+        return Ok(pool.slot0.tick);
+    }
+    Ok((weighted_sum / total_weight).round() as i32)
+}
+
+// Swaps like PoolState::swap() and adds up the fee growth per unit of liquidity the swap contributes
+// to the input token's feeGrowthGlobal, fee_amount * Q128 / liquidity for every step as in the pool
+// contract (without a protocol fee). Returns (result, fee_growth_0, fee_growth_1).
+pub fn total_fee_growth(
+    pool: &PoolState,
+    zero_for_one: bool,
+    amount_specified: I256,
+    fee: u32,
+) -> Result<(SwapResult, U256, U256), UniswapV3MathError> {
+    let (result, trace) = swap_traced(
+        &pool.ticks,
+        &pool.tick_bitmap,
+        pool.tick_spacing,
+        zero_for_one,
+        amount_specified,
+        default_sqrt_price_limit(zero_for_one),
+        &pool.slot0,
+        fee,
+    )?;
+    let mut fee_growth = U256::zero();
+    for step in &trace {
+        if step.liquidity > 0 {
+            fee_growth += full_math::mul_div(
+                step.fee_amount,
+                sqrt_price_math::Q128,
+                U256::from(step.liquidity),
+            )?;
+        }
+    }
+    if zero_for_one {
+        Ok((result, fee_growth, U256::zero()))
+    } else {
+        Ok((result, U256::zero(), fee_growth))
+    }
+}
+
+// returns (uint256 feeGrowth0, uint256 feeGrowth1)
+// The fee growth per unit of liquidity (Q128) the feeGrowthGlobals accumulate over (zero_for_one,
+// amount_specified, fee) swaps applied one after the other at the default price limits, each counted as
+// in total_fee_growth()
+pub fn fee_growth_over_swaps(
+    pool: &PoolState,
+    swaps: &[(bool, I256, u32)],
+) -> Result<(U256, U256), UniswapV3MathError> {
+    let mut pool = pool.clone();
+    let (mut fee_growth_0, mut fee_growth_1) = (U256::zero(), U256::zero());
+    for &(zero_for_one, amount_specified, fee) in swaps {
+        let (result, growth_0, growth_1) =
+            total_fee_growth(&pool, zero_for_one, amount_specified, fee)?;
+        fee_growth_0 += growth_0;
+        fee_growth_1 += growth_1;
+        pool.slot0 = Slot0 {
+            sqrt_price: result.sqrt_price_after,
+            liquidity: result.liquidity_after,
+            tick: result.tick_after,
+        };
+    }
+    Ok((fee_growth_0, fee_growth_1))
+}
+
+// Splits an exact input or output swap without a price limit into the ranges of constant liquidity it
+// went through, as (tick_start, tick_end, amount_in, amount_out) per range. amount_in includes the fee.
+pub fn execution_by_range(
+    pool: &PoolState,
+    zero_for_one: bool,
+    amount_specified: I256,
+    fee: u32,
+) -> Result<Vec<(i32, i32, U256, U256)>, UniswapV3MathError> {
+    let (_, trace) = swap_traced(
+        &pool.ticks,
+        &pool.tick_bitmap,
+        pool.tick_spacing,
+        zero_for_one,
+        amount_specified,
+        default_sqrt_price_limit(zero_for_one),
+        &pool.slot0,
+        fee,
+    )?;
+
+    let mut ranges: Vec<(i32, i32, U256, U256)> = vec![];
+    let mut range_open = false;
+    for step in &trace {
+        if !range_open {
+            ranges.push((step.tick_start, step.tick_end, U256::zero(), U256::zero()));
+            range_open = true;
+        }
+        let range = ranges.last_mut().unwrap();
+        range.1 = step.tick_end;
+        range.2 += step.amount_in + step.fee_amount;
+        range.3 += step.amount_out;
+        // crossing an initialized tick changes the liquidity and ends the range
+        if step.initialized
+            && step.sqrt_price_end_x96 == tick_math::get_sqrt_ratio_at_tick(step.tick_next)?
+        {
+            range_open = false;
+        }
+    }
+    Ok(ranges)
+}
+
+// Serializes a swap trace, e.g. for logging or diffing against Foundry traces
+#[cfg(feature = "serde")]
+pub fn trace_to_json(trace: &[SwapStepTrace]) -> String {
+    serde_json::to_string(trace).expect("swap traces always serialize")
+}
+
+// Everything swap() needs to know about a pool
+#[derive(Clone)]
+pub struct PoolState {
+    pub ticks: HashMap<i32, TickInfo>,
+    pub tick_bitmap: HashMap<i16, U256>,
+    pub tick_spacing: i32,
+    pub slot0: Slot0,
+}
+
+// Read access to the state of a pool, for swap() against pool data that is not a PoolState, e.g.
+// fetched lazily by a router. tick() has to return every tick the bitmap marks as initialized,
+// bitmap_word() zero for words without initialized ticks, fee() the fee tier in hundredths of a bip.
+pub trait PoolSnapshot {
+    fn slot0(&self) -> Slot0;
+    fn tick(&self, tick: i32) -> Option<TickInfo>;
+    fn bitmap_word(&self, word_pos: i16) -> U256;
+    fn tick_spacing(&self) -> i32;
+    fn fee(&self) -> u32;
+}
+
+// A PoolState at a fee tier, as the PoolState itself does not carry the fee
+impl PoolSnapshot for (&PoolState, u32) {
+    fn slot0(&self) -> Slot0 {
+        self.0.slot0.clone()
+    }
+
+    fn tick(&self, tick: i32) -> Option<TickInfo> {
+        self.0.ticks.get(&tick).cloned()
+    }
+
+    fn bitmap_word(&self, word_pos: i16) -> U256 {
+        *self.0.tick_bitmap.get(&word_pos).unwrap_or(&U256::zero())
+    }
+
+    fn tick_spacing(&self) -> i32 {
+        self.0.tick_spacing
+    }
+
+    fn fee(&self) -> u32 {
+        self.1
+    }
+}
+
+impl PoolState {
+    pub fn swap(
+        &self,
+        zero_for_one: bool,
+        amount_specified: I256,
+        sqrt_price_limit: U256,
+        fee: u32,
+    ) -> Result<SwapResult, UniswapV3MathError> {
+        swap(
+            &(self, fee),
+            zero_for_one,
+            amount_specified,
+            sqrt_price_limit,
+        )
+    }
+}
+
+// Checks a pool snapshot for ticks whose l_gross exceeds max_liquidity_per_tick(tick_spacing), which the
+// pool contract never allows and which only a corrupt snapshot contains. Offenders are returned in order.
+pub fn validate_pool(pool: &PoolState) -> Result<(), Vec<i32>> {
+    let max_liquidity = tick::max_liquidity_per_tick(pool.tick_spacing);
+    let mut offenders: Vec<i32> = pool
+        .ticks
+        .iter()
+        .filter(|(_, info)| info.l_gross > max_liquidity)
+        .map(|(&index, _)| index)
+        .collect();
+    if offenders.is_empty() {
+        return Ok(());
+    }
+    offenders.sort_unstable();
+    Err(offenders)
+}
+
+// A pool whose slot0 follows the swaps applied to it
+#[derive(Clone)]
+pub struct MutablePoolState {
+    pub state: PoolState,
+    // oracle accumulators, advanced by advance_time()
+    pub tick_cumulative: i64,
+    pub seconds_per_liquidity_cumulative_x128: U256,
+}
+
+impl MutablePoolState {
+    pub fn new(state: PoolState) -> Self {
+        MutablePoolState {
+            state,
+            tick_cumulative: 0,
+            seconds_per_liquidity_cumulative_x128: U256::zero(),
+        }
+    }
+
+    // Lets `seconds` pass at the current tick and liquidity, like Oracle.transform does on-chain
+    pub fn advance_time(&mut self, seconds: u32) {
+        let slot0 = &self.state.slot0;
+        self.tick_cumulative += slot0.tick as i64 * seconds as i64;
+        // seconds / liquidity in Q128, with zero liquidity counted as 1
+        self.seconds_per_liquidity_cumulative_x128 +=
+            (U256::from(seconds) << 128) / U256::from(slot0.liquidity.max(1));
+    }
+
+    pub fn apply_swap(
+        &mut self,
+        zero_for_one: bool,
+        amount_specified: I256,
+        sqrt_price_limit: U256,
+        fee: u32,
+    ) -> Result<SwapResult, UniswapV3MathError> {
+        let result = self
+            .state
+            .swap(zero_for_one, amount_specified, sqrt_price_limit, fee)?;
+        self.state.slot0 = Slot0 {
+            sqrt_price: result.sqrt_price_after,
+            liquidity: result.liquidity_after,
+            tick: result.tick_after,
+        };
+        Ok(result)
+    }
+}
+
+// The state of the swap loop, as swap_full_trace() returns it after every step
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SwapState {
+    pub amount_specified_remaining: I256,
+    pub amount_calculated: I256,
+    pub sqrt_price_x96: U256,
+    pub tick: i32,
+    pub liquidity: u128,
+    pub fee_amount: U256,
+    pub protocol_fee: U256,
+    pub sqrt_price_min: U256,
+    pub sqrt_price_max: U256,
 }
 
 #[derive(Default)]
@@ -49,7 +462,75 @@ struct StepComputations {
     fee_amount: U256,
 }
 
-pub fn swap(
+// The most extreme sqrt price limit swap() accepts in the given direction
+pub fn default_sqrt_price_limit(zero_for_one: bool) -> U256 {
+    if zero_for_one {
+        tick_math::MIN_SQRT_RATIO + 1
+    } else {
+        tick_math::MAX_SQRT_RATIO - 1
+    }
+}
+
+// The zero_for_one a sqrt price limit implies: selling token0 for a limit below the current price,
+// selling token1 for one above it. None for a limit at the current price or one that swap() rejects
+// in either direction.
+pub fn direction_for_limit(slot0: &Slot0, sqrt_price_limit: U256) -> Option<bool> {
+    if sqrt_price_limit <= tick_math::MIN_SQRT_RATIO
+        || sqrt_price_limit >= tick_math::MAX_SQRT_RATIO
+    {
+        return None;
+    }
+    if sqrt_price_limit < slot0.sqrt_price {
+        Some(true)
+    } else if sqrt_price_limit > slot0.sqrt_price {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+// Swaps against any PoolSnapshot at its fee. Reads only the bitmap words the swap goes through and the
+// ticks it crosses, in the order the pool contract would.
+// The loop always terminates: a step either moves the price to its target, the next tick or the limit,
+// or uses up the amount left, the exact input remainder the step can not swap being taken as fee. So
+// however small amount_specified is, the swap returns; one too small for any output has an output of
+// zero, e.g. a 1 wei exact input swap is all fee.
+pub fn swap<P>(
+    pool: &P,
+    zero_for_one: bool,
+    amount_specified: I256,
+    sqrt_price_limit: U256,
+) -> Result<SwapResult, UniswapV3MathError>
+where
+    P: PoolSnapshot,
+{
+    let tick_spacing = pool.tick_spacing();
+    if tick_spacing <= 0 {
+        return Err(UniswapV3MathError::InvalidTickSpacing(tick_spacing));
+    }
+    let (result, _) = swap_loop(
+        |tick| pool.tick(tick),
+        |tick, lte| {
+            tick_bitmap::next_initialized_tick_within_one_word_with(
+                |word_pos| pool.bitmap_word(word_pos),
+                tick,
+                tick_spacing,
+                lte,
+            )
+        },
+        zero_for_one,
+        amount_specified,
+        sqrt_price_limit,
+        &pool.slot0(),
+        pool.fee(),
+        (0, 0),
+        |_, _| true,
+    )?;
+    Ok(result)
+}
+
+// Same as swap(), additionally returning the trace of every step of the swap loop
+pub fn swap_traced(
     ticks: &HashMap<i32, TickInfo>,
     tick_bitmap: &HashMap<i16, U256>,
     tick_spacing: i32,
@@ -58,7 +539,373 @@ pub fn swap(
     sqrt_price_limit: U256,
     slot0: &Slot0,
     fee: u32,
+) -> Result<(SwapResult, Vec<SwapStepTrace>), UniswapV3MathError> {
+    let mut trace = vec![];
+    let (result, _) = swap_with_observer(
+        ticks,
+        tick_bitmap,
+        tick_spacing,
+        zero_for_one,
+        amount_specified,
+        sqrt_price_limit,
+        slot0,
+        fee,
+        |step| {
+            trace.push(step.clone());
+            true
+        },
+    )?;
+    Ok((result, trace))
+}
+
+// Same as swap(), additionally returning the full state of the swap loop after every step, for
+// property tests and debugging. The last state is the one the result is made of.
+pub fn swap_full_trace(
+    ticks: &HashMap<i32, TickInfo>,
+    tick_bitmap: &HashMap<i16, U256>,
+    tick_spacing: i32,
+    zero_for_one: bool,
+    amount_specified: I256,
+    sqrt_price_limit: U256,
+    slot0: &Slot0,
+    fee: u32,
+) -> Result<(SwapResult, Vec<SwapState>), UniswapV3MathError> {
+    if tick_spacing <= 0 {
+        return Err(UniswapV3MathError::InvalidTickSpacing(tick_spacing));
+    }
+    let mut states = vec![];
+    let (result, _) = swap_loop(
+        |tick| ticks.get(&tick).cloned(),
+        |tick, lte| {
+            tick_bitmap::next_initialized_tick_within_one_word(tick_bitmap, tick, tick_spacing, lte)
+        },
+        zero_for_one,
+        amount_specified,
+        sqrt_price_limit,
+        slot0,
+        fee,
+        (0, 0),
+        |_, state| {
+            states.push(state.clone());
+            true
+        },
+    )?;
+    Ok((result, states))
+}
+
+// Error of swap_with_word_limit
+#[derive(Debug)]
+pub enum BoundedSwapError {
+    Math(UniswapV3MathError),
+    // the swap needed more bitmap words than allowed, carries the swap up to the last allowed read
+    WordReadLimitExceeded(SwapResult),
+}
+
+impl From<UniswapV3MathError> for BoundedSwapError {
+    fn from(err: UniswapV3MathError) -> Self {
+        BoundedSwapError::Math(err)
+    }
+}
+
+// Same as swap(), reading at most max_word_reads distinct words of the tick bitmap, counted like the
+// word_reads of complexity(). Every step of the swap loop reads a word, whether or not it holds an
+// initialized tick, so sparse pools need more reads than tick crossings; steps within a word already
+// read do not count again.
+pub fn swap_with_word_limit(
+    ticks: &HashMap<i32, TickInfo>,
+    tick_bitmap: &HashMap<i16, U256>,
+    tick_spacing: i32,
+    zero_for_one: bool,
+    amount_specified: I256,
+    sqrt_price_limit: U256,
+    slot0: &Slot0,
+    fee: u32,
+    max_word_reads: usize,
+) -> Result<SwapResult, BoundedSwapError> {
+    if tick_spacing <= 0 {
+        return Err(UniswapV3MathError::InvalidTickSpacing(tick_spacing).into());
+    }
+    let (result, completed) = if max_word_reads == 0 {
+        swap_with_step_budget(
+            ticks,
+            tick_bitmap,
+            tick_spacing,
+            zero_for_one,
+            amount_specified,
+            sqrt_price_limit,
+            slot0,
+            fee,
+            0,
+        )?
+    } else {
+        let mut words = HashSet::new();
+        words.insert(bitmap_word_read(slot0.tick, tick_spacing, zero_for_one));
+        swap_with_observer(
+            ticks,
+            tick_bitmap,
+            tick_spacing,
+            zero_for_one,
+            amount_specified,
+            sqrt_price_limit,
+            slot0,
+            fee,
+            |step| {
+                // the next step starts where this one ended, stop before it reads a word too many
+                let word = bitmap_word_read(step.tick_end, tick_spacing, zero_for_one);
+                words.contains(&word) || (words.len() < max_word_reads && words.insert(word))
+            },
+        )?
+    };
+    if !completed {
+        return Err(BoundedSwapError::WordReadLimitExceeded(result));
+    }
+    Ok(result)
+}
+
+// Same as PoolState::swap(), running at most max_iterations steps of the swap loop. Returns the swap
+// so far and whether it completed within the budget.
+pub fn swap_budgeted(
+    pool: &PoolState,
+    zero_for_one: bool,
+    amount_specified: I256,
+    sqrt_price_limit: U256,
+    fee: u32,
+    max_iterations: usize,
+) -> Result<(SwapResult, bool), UniswapV3MathError> {
+    swap_with_step_budget(
+        &pool.ticks,
+        &pool.tick_bitmap,
+        pool.tick_spacing,
+        zero_for_one,
+        amount_specified,
+        sqrt_price_limit,
+        &pool.slot0,
+        fee,
+        max_iterations,
+    )
+}
+
+fn swap_with_step_budget(
+    ticks: &HashMap<i32, TickInfo>,
+    tick_bitmap: &HashMap<i16, U256>,
+    tick_spacing: i32,
+    zero_for_one: bool,
+    amount_specified: I256,
+    sqrt_price_limit: U256,
+    slot0: &Slot0,
+    fee: u32,
+    max_steps: usize,
+) -> Result<(SwapResult, bool), UniswapV3MathError> {
+    if max_steps == 0 {
+        let result = SwapResult {
+            sqrt_price_after: slot0.sqrt_price,
+            liquidity_after: slot0.liquidity,
+            tick_after: slot0.tick,
+            sqrt_price_min: slot0.sqrt_price,
+            sqrt_price_max: slot0.sqrt_price,
+            ..Default::default()
+        };
+        return Ok((result, amount_specified.is_zero()));
+    }
+    let mut steps = 0;
+    swap_with_observer(
+        ticks,
+        tick_bitmap,
+        tick_spacing,
+        zero_for_one,
+        amount_specified,
+        sqrt_price_limit,
+        slot0,
+        fee,
+        |_| {
+            steps += 1;
+            steps < max_steps
+        },
+    )
+}
+
+// The word of the tick bitmap next_initialized_tick_within_one_word reads for a tick and lte
+fn bitmap_word_read(tick: i32, tick_spacing: i32, lte: bool) -> i32 {
+    (tick.div_euclid(tick_spacing) + if lte { 0 } else { 1 }) >> 8
+}
+
+// Cost profile of a swap
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SwapComplexity {
+    // initialized ticks crossed, each updating the tick's storage
+    pub tick_crossings: usize,
+    // distinct tick bitmap words read, each a cold storage read
+    pub word_reads: usize,
+    // iterations of the swap loop, each reading a word, possibly one read by an earlier step
+    pub steps: usize,
+}
+
+// The cost profile of a swap at the default price limits, counted while swapping rather than estimated
+pub fn complexity(
+    pool: &PoolState,
+    zero_for_one: bool,
+    amount_specified: I256,
+    fee: u32,
+) -> Result<SwapComplexity, UniswapV3MathError> {
+    let tick_spacing = pool.tick_spacing;
+    if tick_spacing <= 0 {
+        return Err(UniswapV3MathError::InvalidTickSpacing(tick_spacing));
+    }
+    let mut words = HashSet::new();
+    let mut trace = vec![];
+    swap_loop(
+        |tick| pool.ticks.get(&tick).cloned(),
+        |tick, lte| {
+            words.insert(bitmap_word_read(tick, tick_spacing, lte));
+            tick_bitmap::next_initialized_tick_within_one_word(
+                &pool.tick_bitmap,
+                tick,
+                tick_spacing,
+                lte,
+            )
+        },
+        zero_for_one,
+        amount_specified,
+        default_sqrt_price_limit(zero_for_one),
+        &pool.slot0,
+        fee,
+        (0, 0),
+        |step, _| {
+            trace.push(step.clone());
+            true
+        },
+    )?;
+
+    let mut tick_crossings = 0;
+    for step in &trace {
+        if step.initialized
+            && step.sqrt_price_end_x96 == tick_math::get_sqrt_ratio_at_tick(step.tick_next)?
+        {
+            tick_crossings += 1;
+        }
+    }
+    Ok(SwapComplexity {
+        tick_crossings,
+        word_reads: words.len(),
+        steps: trace.len(),
+    })
+}
+
+// The swap loop. `on_step` is called after every step and stops the swap early by returning false.
+// Also returns whether the swap ran to completion, i.e. it was not stopped while amount was left
+// to swap before the price limit.
+fn swap_with_observer<F>(
+    ticks: &HashMap<i32, TickInfo>,
+    tick_bitmap: &HashMap<i16, U256>,
+    tick_spacing: i32,
+    zero_for_one: bool,
+    amount_specified: I256,
+    sqrt_price_limit: U256,
+    slot0: &Slot0,
+    fee: u32,
+    mut on_step: F,
+) -> Result<(SwapResult, bool), UniswapV3MathError>
+where
+    F: FnMut(&SwapStepTrace) -> bool,
+{
+    if tick_spacing <= 0 {
+        return Err(UniswapV3MathError::InvalidTickSpacing(tick_spacing));
+    }
+    swap_loop(
+        |tick| ticks.get(&tick).cloned(),
+        |tick, lte| {
+            tick_bitmap::next_initialized_tick_within_one_word(tick_bitmap, tick, tick_spacing, lte)
+        },
+        zero_for_one,
+        amount_specified,
+        sqrt_price_limit,
+        slot0,
+        fee,
+        (0, 0),
+        |step, _| on_step(step),
+    )
+}
+
+// Same as swap(), finding the next initialized tick in an ascending list of the initialized ticks
+// instead of a bitmap. Gives the same result as swap() on the matching bitmap.
+pub fn swap_with_tick_list(
+    ticks: &HashMap<i32, TickInfo>,
+    sorted_initialized: &[i32],
+    tick_spacing: i32,
+    zero_for_one: bool,
+    amount_specified: I256,
+    sqrt_price_limit: U256,
+    slot0: &Slot0,
+    fee: u32,
+) -> Result<SwapResult, UniswapV3MathError> {
+    if tick_spacing <= 0 {
+        return Err(UniswapV3MathError::InvalidTickSpacing(tick_spacing));
+    }
+    let (result, _) = swap_loop(
+        |tick| ticks.get(&tick).cloned(),
+        |tick, lte| {
+            Ok(
+                tick_bitmap::next_initialized_tick_within_one_word_from_list(
+                    sorted_initialized,
+                    tick,
+                    tick_spacing,
+                    lte,
+                ),
+            )
+        },
+        zero_for_one,
+        amount_specified,
+        sqrt_price_limit,
+        slot0,
+        fee,
+        (0, 0),
+        |_, _| true,
+    )?;
+    Ok(result)
+}
+
+// Same as swap(), taking the protocol fee like a pool whose slot0.feeProtocol is fee_protocol_0 in the
+// low and fee_protocol_1 in the high 4 bits: 1 / fee_protocol_0 of the fee when selling token0 and
+// 1 / fee_protocol_1 when selling token1, none for a zero denominator. The result's protocol_fee is the
+// part of fee_amount that is taken, so the liquidity providers earn fee_amount - protocol_fee.
+pub fn swap_with_protocol_fee(
+    ticks: &HashMap<i32, TickInfo>,
+    tick_bitmap: &HashMap<i16, U256>,
+    tick_spacing: i32,
+    zero_for_one: bool,
+    amount_specified: I256,
+    sqrt_price_limit: U256,
+    slot0: &Slot0,
+    fee: u32,
+    fee_protocol_0: u8,
+    fee_protocol_1: u8,
 ) -> Result<SwapResult, UniswapV3MathError> {
+    if tick_spacing <= 0 {
+        return Err(UniswapV3MathError::InvalidTickSpacing(tick_spacing));
+    }
+    let (result, _) = swap_loop(
+        |tick| ticks.get(&tick).cloned(),
+        |tick, lte| {
+            tick_bitmap::next_initialized_tick_within_one_word(tick_bitmap, tick, tick_spacing, lte)
+        },
+        zero_for_one,
+        amount_specified,
+        sqrt_price_limit,
+        slot0,
+        fee,
+        (fee_protocol_0, fee_protocol_1),
+        |_, _| true,
+    )?;
+    Ok(result)
+}
+
+// The require on sqrtPriceLimitX96 of UniswapV3Pool.swap: the limit has to be within the sqrt ratio
+// range and on the side of the current price the swap moves it to
+pub(crate) fn check_sqrt_price_limit(
+    zero_for_one: bool,
+    sqrt_price_limit: U256,
+    sqrt_price: U256,
+) -> Result<(), UniswapV3MathError> {
     if sqrt_price_limit <= tick_math::MIN_SQRT_RATIO {
         return Err(UniswapV3MathError::SplM);
     }
@@ -66,31 +913,71 @@ pub fn swap(
         return Err(UniswapV3MathError::SpuM);
     }
     if zero_for_one {
-        if sqrt_price_limit >= slot0.sqrt_price {
+        if sqrt_price_limit >= sqrt_price {
             return Err(UniswapV3MathError::SplC);
         }
     } else {
-        if sqrt_price_limit <= slot0.sqrt_price {
+        if sqrt_price_limit <= sqrt_price {
             return Err(UniswapV3MathError::SpuC);
         }
     }
+    Ok(())
+}
+
+// The swap loop itself, `get_tick` looks up the initialized ticks crossed and `next_initialized_tick`
+// returns the next initialized tick within one word as (tick, initialized) for a tick and lte
+fn swap_loop<T, N, F>(
+    mut get_tick: T,
+    mut next_initialized_tick: N,
+    zero_for_one: bool,
+    amount_specified: I256,
+    sqrt_price_limit: U256,
+    slot0: &Slot0,
+    fee: u32,
+    fee_protocol: (u8, u8),
+    mut on_step: F,
+) -> Result<(SwapResult, bool), UniswapV3MathError>
+where
+    T: FnMut(i32) -> Option<TickInfo>,
+    N: FnMut(i32, bool) -> Result<(i32, bool), UniswapV3MathError>,
+    F: FnMut(&SwapStepTrace, &SwapState) -> bool,
+{
+    check_sqrt_price_limit(zero_for_one, sqrt_price_limit, slot0.sqrt_price)?;
     let exact_input = amount_specified.is_positive();
+    // the protocol fee is taken in the input token
+    let fee_protocol = if zero_for_one {
+        fee_protocol.0
+    } else {
+        fee_protocol.1
+    };
     let mut state = SwapState {
         amount_specified_remaining: amount_specified,
         amount_calculated: I256::zero(),
         sqrt_price_x96: slot0.sqrt_price,
         tick: slot0.tick,
         liquidity: slot0.liquidity,
+        fee_amount: U256::zero(),
+        protocol_fee: U256::zero(),
+        sqrt_price_min: slot0.sqrt_price,
+        sqrt_price_max: slot0.sqrt_price,
     };
+    let mut completed = true;
     while !state.amount_specified_remaining.is_zero() && state.sqrt_price_x96 != sqrt_price_limit {
+        // the price can not move past the extremes of the tick range, stop at them even if the limit
+        // has not been reached
+        let at_boundary = if zero_for_one {
+            state.sqrt_price_x96 <= tick_math::MIN_SQRT_RATIO
+        } else {
+            state.sqrt_price_x96 >= tick_math::MAX_SQRT_RATIO
+        };
+        if at_boundary {
+            break;
+        }
         let mut step = StepComputations::default();
         step.sqrt_price_start_x96 = state.sqrt_price_x96;
-        (step.tick_next, step.initialized) = tick_bitmap::next_initialized_tick_within_one_word(
-            tick_bitmap,
-            state.tick,
-            tick_spacing,
-            zero_for_one,
-        )?;
+        let tick_start = state.tick;
+        let liquidity_start = state.liquidity;
+        (step.tick_next, step.initialized) = next_initialized_tick(state.tick, zero_for_one)?;
         if step.tick_next < tick_math::MIN_TICK {
             step.tick_next = tick_math::MIN_TICK;
         } else if step.tick_next > tick_math::MAX_TICK {
@@ -132,11 +1019,21 @@ pub fn swap(
             state.amount_calculated =
                 state.amount_calculated + I256::from_raw(step.amount_in + step.fee_amount);
         }
-        // 不计算protocol fee
+        state.fee_amount = state.fee_amount + step.fee_amount;
+        // the protocol takes 1 / fee_protocol of the fee of every step, rounded down
+        if fee_protocol > 0 {
+            state.protocol_fee = state.protocol_fee + step.fee_amount / U256::from(fee_protocol);
+        }
+        state.sqrt_price_min = state.sqrt_price_min.min(state.sqrt_price_x96);
+        state.sqrt_price_max = state.sqrt_price_max.max(state.sqrt_price_x96);
         if state.sqrt_price_x96 == step.sqrt_price_next_x96 {
+            // only the bitmap decides whether a tick is initialized, entries of ticks the bitmap
+            // does not mark are stale and their l_net is not applied
             if step.initialized {
                 // initialized tick一定存在于ticks里
-                let mut l_net = ticks.get(&step.tick_next).unwrap().l_net;
+                let mut l_net = get_tick(step.tick_next)
+                    .ok_or(UniswapV3MathError::TickNotFound(step.tick_next))?
+                    .l_net;
                 if zero_for_one {
                     l_net = -1 * l_net;
                 }
@@ -150,6 +1047,23 @@ pub fn swap(
         } else if state.sqrt_price_x96 != step.sqrt_price_start_x96 {
             state.tick = tick_math::get_tick_at_sqrt_ratio(state.sqrt_price_x96)?;
         }
+        let step_trace = SwapStepTrace {
+            sqrt_price_start_x96: step.sqrt_price_start_x96,
+            sqrt_price_end_x96: state.sqrt_price_x96,
+            tick_start,
+            tick_end: state.tick,
+            tick_next: step.tick_next,
+            initialized: step.initialized,
+            liquidity: liquidity_start,
+            amount_in: step.amount_in,
+            amount_out: step.amount_out,
+            fee_amount: step.fee_amount,
+        };
+        if !on_step(&step_trace, &state) {
+            completed = state.amount_specified_remaining.is_zero()
+                || state.sqrt_price_x96 == sqrt_price_limit;
+            break;
+        }
     }
     let amount0_delta;
     let amount1_delta;
@@ -160,11 +1074,3117 @@ pub fn swap(
         amount0_delta = state.amount_calculated;
         amount1_delta = amount_specified - state.amount_specified_remaining;
     }
-    return Ok(SwapResult {
+    let result = SwapResult {
         amount0_delta,
         amount1_delta,
         sqrt_price_after: state.sqrt_price_x96,
         liquidity_after: state.liquidity,
         tick_after: state.tick,
-    });
+        fee_amount: state.fee_amount,
+        protocol_fee: state.protocol_fee,
+        sqrt_price_min: state.sqrt_price_min,
+        sqrt_price_max: state.sqrt_price_max,
+    };
+    Ok((result, completed))
+}
+
+// Replays the Swap logs of a block against a pool of the given fee tier in order. Each log is applied
+// as an exact input swap of the token the pool received, limited to the sqrt price the log reports.
+pub fn replay_block(
+    pool: &mut MutablePoolState,
+    fee: u32,
+    swap_logs: &[Log],
+) -> Result<Vec<SwapResult>, UniswapV3MathError> {
+    let mut results = Vec::with_capacity(swap_logs.len());
+    for log in swap_logs {
+        let swap_log = ethers::contract::parse_log::<abi::SwapFilter>(log.clone())
+            .map_err(|err| UniswapV3MathError::SwapLogDecodeError(err.to_string()))?;
+
+        let zero_for_one = swap_log.amount_0.is_positive();
+        let amount_specified = if zero_for_one {
+            swap_log.amount_0
+        } else {
+            swap_log.amount_1
+        };
+
+        let sqrt_price = pool.state.slot0.sqrt_price;
+        let moves_price = if zero_for_one {
+            swap_log.sqrt_price_x96 < sqrt_price
+        } else {
+            swap_log.sqrt_price_x96 > sqrt_price
+        };
+        let sqrt_price_limit = if moves_price {
+            swap_log.sqrt_price_x96
+        } else {
+            default_sqrt_price_limit(zero_for_one)
+        };
+
+        results.push(pool.apply_swap(zero_for_one, amount_specified, sqrt_price_limit, fee)?);
+    }
+    Ok(results)
+}
+
+// The price of the pool in both directions, adjusted for the token decimals, as
+// (token0 per token1, token1 per token0). One is the reciprocal of the other.
+pub fn prices(slot0: &Slot0, decimals_0: u8, decimals_1: u8) -> (f64, f64) {
+    let sqrt_price = u256_to_f64(slot0.sqrt_price) / 2f64.powi(96);
+    let token1_per_token0 =
+        sqrt_price * sqrt_price * 10f64.powi(decimals_0 as i32 - decimals_1 as i32);
+    (1f64 / token1_per_token0, token1_per_token0)
+}
+
+// Price distance between the initialized ticks surrounding the current tick, i.e. the width of the
+// band in which the current liquidity stays active. Falls back to MIN_TICK / MAX_TICK on a side
+// without initialized ticks.
+pub fn active_range_price_width(
+    pool: &PoolState,
+    decimals_0: u8,
+    decimals_1: u8,
+) -> Result<f64, UniswapV3MathError> {
+    let tick_lower = tick_bitmap::next_initialized_tick(
+        &pool.tick_bitmap,
+        pool.slot0.tick,
+        pool.tick_spacing,
+        true,
+    )?
+    .unwrap_or(tick_math::MIN_TICK);
+    let tick_upper = tick_bitmap::next_initialized_tick(
+        &pool.tick_bitmap,
+        pool.slot0.tick,
+        pool.tick_spacing,
+        false,
+    )?
+    .unwrap_or(tick_math::MAX_TICK);
+
+    Ok(tick_to_price(tick_upper, decimals_0, decimals_1)
+        - tick_to_price(tick_lower, decimals_0, decimals_1))
+}
+
+// Active liquidity at current_tick rebuilt from ticks alone, by adding up l_net from the lowest tick to
+// current_tick. The authoritative check for slot0.liquidity.
+pub fn reconstruct_active_liquidity(
+    ticks: &HashMap<i32, TickInfo>,
+    current_tick: i32,
+) -> Result<u128, UniswapV3MathError> {
+    let mut indexes: Vec<i32> = ticks
+        .keys()
+        .copied()
+        .filter(|&index| index <= current_tick)
+        .collect();
+    indexes.sort_unstable();
+
+    let mut liquidity = 0;
+    for index in indexes {
+        liquidity = liquidity_math::add_delta(liquidity, ticks[&index].l_net)?;
+    }
+    Ok(liquidity)
+}
+
+// Copy of the pool with every liquidity scaled by factor_bps / 10000. l_net is rescaled from the
+// cumulative liquidity of each range, so that rounding cannot make a range negative.
+fn scale_liquidity(pool: &PoolState, factor_bps: u32) -> Result<PoolState, UniswapV3MathError> {
+    let scale = |liquidity: u128| -> u128 {
+        (U256::from(liquidity) * U256::from(factor_bps) / U256::from(10000))
+            .min(U256::from(u128::MAX))
+            .as_u128()
+    };
+
+    let mut indexes: Vec<i32> = pool.ticks.keys().copied().collect();
+    indexes.sort_unstable();
+
+    let mut scaled = pool.clone();
+    let (mut liquidity, mut scaled_liquidity) = (0u128, 0u128);
+    for index in indexes {
+        let info = scaled.ticks.get_mut(&index).unwrap();
+        liquidity = liquidity_math::add_delta(liquidity, info.l_net)?;
+        let next_scaled_liquidity = scale(liquidity);
+        info.l_net = next_scaled_liquidity as i128 - scaled_liquidity as i128;
+        info.l_gross = scale(info.l_gross);
+        scaled_liquidity = next_scaled_liquidity;
+    }
+    scaled.slot0.liquidity = scale(pool.slot0.liquidity);
+    Ok(scaled)
+}
+
+// Range of the output of a swap when every liquidity of the pool is only known to within
+// liquidity_uncertainty_bps: (output with liquidity scaled down, output with liquidity scaled up)
+pub fn output_bounds(
+    pool: &PoolState,
+    zero_for_one: bool,
+    amount_specified: I256,
+    fee: u32,
+    liquidity_uncertainty_bps: u32,
+) -> Result<(U256, U256), UniswapV3MathError> {
+    let sqrt_price_limit = default_sqrt_price_limit(zero_for_one);
+    let mut outputs = [U256::zero(); 2];
+    for (output, factor_bps) in outputs.iter_mut().zip([
+        10000u32.saturating_sub(liquidity_uncertainty_bps),
+        10000u32.saturating_add(liquidity_uncertainty_bps),
+    ]) {
+        let result = scale_liquidity(pool, factor_bps)?.swap(
+            zero_for_one,
+            amount_specified,
+            sqrt_price_limit,
+            fee,
+        )?;
+        *output = result.io(zero_for_one).1;
+    }
+    Ok((outputs[0], outputs[1]))
+}
+
+// Largest input, fee included, that keeps the price short of the next initialized tick in the swap
+// direction, so the active liquidity does not change. One more unit reaches the tick and crosses it.
+pub fn max_amount_in_current_tick(
+    pool: &PoolState,
+    zero_for_one: bool,
+    fee: u32,
+) -> Result<U256, UniswapV3MathError> {
+    let sqrt_price_limit = match tick_bitmap::next_initialized_tick(
+        &pool.tick_bitmap,
+        pool.slot0.tick,
+        pool.tick_spacing,
+        zero_for_one,
+    )? {
+        Some(tick_next) => {
+            let sqrt_price_next = tick_math::get_sqrt_ratio_at_tick(tick_next)?;
+            if zero_for_one {
+                sqrt_price_next.max(default_sqrt_price_limit(true))
+            } else {
+                sqrt_price_next.min(default_sqrt_price_limit(false))
+            }
+        }
+        None => default_sqrt_price_limit(zero_for_one),
+    };
+    // the price sits right on the next tick, any input crosses it
+    if zero_for_one && sqrt_price_limit >= pool.slot0.sqrt_price {
+        return Ok(U256::zero());
+    }
+
+    let result = pool.swap(zero_for_one, I256::MAX, sqrt_price_limit, fee)?;
+    Ok(result.io(zero_for_one).0.saturating_sub(U256::one()))
+}
+
+// Quotes an exact input swap without a price limit and returns the output that is still
+// guaranteed after slippage_bps of slippage
+pub fn min_received(
+    pool: &PoolState,
+    zero_for_one: bool,
+    amount_in: U256,
+    slippage_bps: u32,
+    fee: u32,
+) -> Result<U256, UniswapV3MathError> {
+    let result = pool.swap(
+        zero_for_one,
+        I256::from_raw(amount_in),
+        default_sqrt_price_limit(zero_for_one),
+        fee,
+    )?;
+    let amount_out = if zero_for_one {
+        (-result.amount1_delta).into_raw()
+    } else {
+        (-result.amount0_delta).into_raw()
+    };
+    full_math::mul_div(
+        amount_out,
+        U256::from(10000 - slippage_bps.min(10000)),
+        U256::from(10000),
+    )
+}
+
+// Input needed to move the price of the pool by move_bps, as (buy_depth, sell_depth): token1 in to
+// raise the price by move_bps and token0 in to lower it by move_bps. Fee included.
+pub fn depth_at_price_move(
+    pool: &PoolState,
+    move_bps: u32,
+    fee: u32,
+) -> Result<(U256, U256), UniswapV3MathError> {
+    // both targets would be the current price, which swap() does not take as a limit
+    if move_bps == 0 {
+        return Ok((U256::zero(), U256::zero()));
+    }
+    // sqrt(1 ± move_bps / 10000) in Q96
+    let sqrt_factor = |bps: u32| sqrt_u256((U256::from(bps) << 192) / U256::from(10000));
+    let sqrt_price = pool.slot0.sqrt_price;
+
+    let sqrt_price_up = full_math::mul_div(
+        sqrt_price,
+        sqrt_factor(move_bps.saturating_add(10000)),
+        sqrt_price_math::Q96,
+    )?
+    .min(default_sqrt_price_limit(false));
+    let sqrt_price_down = full_math::mul_div(
+        sqrt_price,
+        sqrt_factor(10000 - move_bps.min(10000)),
+        sqrt_price_math::Q96,
+    )?
+    .max(default_sqrt_price_limit(true));
+
+    // swap everything there is up to the target price
+    let buy = pool.swap(false, I256::MAX, sqrt_price_up, fee)?;
+    let sell = pool.swap(true, I256::MAX, sqrt_price_down, fee)?;
+    Ok((buy.amount1_delta.into_raw(), sell.amount0_delta.into_raw()))
+}
+
+// The swap on pool_a that brings its price to the current price of pool_b, as (zero_for_one, amount_in).
+// amount_in is fee included and zero if both pools are at the same price.
+pub fn equalizing_swap(
+    pool_a: &PoolState,
+    pool_b: &PoolState,
+    fee: u32,
+) -> Result<(bool, U256), UniswapV3MathError> {
+    let sqrt_price_a = pool_a.slot0.sqrt_price;
+    let sqrt_price_b = pool_b.slot0.sqrt_price;
+    // selling token0 lowers the price
+    let zero_for_one = sqrt_price_a > sqrt_price_b;
+    if sqrt_price_a == sqrt_price_b {
+        return Ok((zero_for_one, U256::zero()));
+    }
+
+    // swap everything there is up to the price of pool_b
+    let result = pool_a.swap(zero_for_one, I256::MAX, sqrt_price_b, fee)?;
+    let amount_in = if zero_for_one {
+        result.amount0_delta
+    } else {
+        result.amount1_delta
+    };
+    Ok((zero_for_one, amount_in.into_raw()))
+}
+
+// Whether the swap would stop at sqrt_price_limit with part of amount_specified left unswapped. The
+// default price limit never binds: a swap that reaches it has run out of liquidity, not into a limit.
+pub fn is_limit_binding(
+    pool: &PoolState,
+    zero_for_one: bool,
+    amount_specified: I256,
+    sqrt_price_limit: U256,
+    fee: u32,
+) -> Result<bool, UniswapV3MathError> {
+    let result = pool.swap(zero_for_one, amount_specified, sqrt_price_limit, fee)?;
+    if sqrt_price_limit == default_sqrt_price_limit(zero_for_one) {
+        return Ok(false);
+    }
+    let amount_swapped = if zero_for_one == amount_specified.is_positive() {
+        result.amount0_delta
+    } else {
+        result.amount1_delta
+    };
+    Ok(amount_swapped != amount_specified)
+}
+
+// Sqrt price after swapping fraction (within [0, 1]) of amount_specified without a price limit
+pub fn price_at_fraction(
+    pool: &PoolState,
+    zero_for_one: bool,
+    amount_specified: I256,
+    fee: u32,
+    fraction: f64,
+) -> Result<U256, UniswapV3MathError> {
+    // fraction in 1e18 fixed point
+    let scale = U256::exp10(18);
+    let fraction = U256::from((fraction.clamp(0f64, 1f64) * 1e18) as u128);
+    let amount = full_math::mul_div(amount_specified.unsigned_abs(), fraction, scale)?;
+    let amount = if amount_specified.is_negative() {
+        -I256::from_raw(amount)
+    } else {
+        I256::from_raw(amount)
+    };
+
+    let result = pool.swap(
+        zero_for_one,
+        amount,
+        default_sqrt_price_limit(zero_for_one),
+        fee,
+    )?;
+    Ok(result.sqrt_price_after)
+}
+
+// Output per unit of input for an infinitesimal swap at the current price, i.e. the spot price in the
+// swap direction net of the fee
+pub fn marginal_output(pool: &PoolState, zero_for_one: bool, fee: u32) -> f64 {
+    let sqrt_price = u256_to_f64(pool.slot0.sqrt_price) / 2f64.powi(96);
+    // token1 per token0
+    let price = sqrt_price * sqrt_price;
+    let spot = if zero_for_one { price } else { 1f64 / price };
+    spot * (1f64 - fee as f64 / 1e6)
+}
+
+// The exact input size, fee included, from which the price impact costs as much as the fee. The impact
+// is the input net of the fee less the output valued at the spot price, both in the input token, and
+// grows faster than the linear fee, so the size is found by bisection. Within one range of liquidity L
+// it is about L * fee / (1 - 2 * fee) net of the fee. The amounts are raw, so token decimals do not
+// matter. The largest fillable input if the impact never catches up, zero for an empty pool.
+pub fn fee_equals_impact_size(
+    pool: &PoolState,
+    zero_for_one: bool,
+    fee: u32,
+) -> Result<U256, UniswapV3MathError> {
+    let spot = marginal_output(pool, zero_for_one, 0);
+    let impact_exceeds_fee = |amount_in: U256| -> Result<bool, UniswapV3MathError> {
+        let result = pool.swap(
+            zero_for_one,
+            I256::from_raw(amount_in),
+            default_sqrt_price_limit(zero_for_one),
+            fee,
+        )?;
+        let (amount_in, amount_out) = result.io(zero_for_one);
+        let amount_in_less_fee = u256_to_f64(amount_in - result.fee_amount);
+        let impact = amount_in_less_fee - u256_to_f64(amount_out) / spot;
+        Ok(impact >= u256_to_f64(result.fee_amount))
+    };
+
+    let mut high = pool
+        .swap(
+            zero_for_one,
+            I256::MAX,
+            default_sqrt_price_limit(zero_for_one),
+            fee,
+        )?
+        .io(zero_for_one)
+        .0;
+    if high.is_zero() || !impact_exceeds_fee(high)? {
+        return Ok(high);
+    }
+    let mut low = U256::zero();
+    while high - low > U256::one() {
+        let mid = low + (high - low) / 2;
+        if impact_exceeds_fee(mid)? {
+            high = mid;
+        } else {
+            low = mid;
+        }
+    }
+    Ok(high)
+}
+
+// The sqrt price limit, as far from the current price as possible, at which swapping amount_specified
+// keeps the average price within max_avg_price. The average price is the input paid per output received,
+// fee included and adjusted for the decimals, so it only gets worse as the limit lets the swap go further
+// and the limit is found by bisection. The default price limit if the whole swap stays within
+// max_avg_price, None if not even the limit one past the current sqrt price does.
+pub fn limit_for_avg_price(
+    pool: &PoolState,
+    zero_for_one: bool,
+    amount_specified: I256,
+    max_avg_price: f64,
+    decimals_0: u8,
+    decimals_1: u8,
+    fee: u32,
+) -> Result<Option<U256>, UniswapV3MathError> {
+    let (decimals_in, decimals_out) = if zero_for_one {
+        (decimals_0, decimals_1)
+    } else {
+        (decimals_1, decimals_0)
+    };
+    let within_max = |sqrt_price_limit: U256| -> Result<bool, UniswapV3MathError> {
+        let result = pool.swap(zero_for_one, amount_specified, sqrt_price_limit, fee)?;
+        let (amount_in, amount_out) = result.io(zero_for_one);
+        if amount_out.is_zero() {
+            return Ok(amount_in.is_zero());
+        }
+        let avg_price = u256_to_f64(amount_in) / u256_to_f64(amount_out)
+            * 10f64.powi(decimals_out as i32 - decimals_in as i32);
+        Ok(avg_price <= max_avg_price)
+    };
+
+    // near always stays within max_avg_price, far never does
+    let mut near = pool.slot0.sqrt_price;
+    let mut far = default_sqrt_price_limit(zero_for_one);
+    if within_max(far)? {
+        return Ok(Some(far));
+    }
+    loop {
+        let distance = if zero_for_one { near - far } else { far - near };
+        if distance <= U256::one() {
+            // the current price itself is rejected as a limit by swap()
+            if near == pool.slot0.sqrt_price {
+                return Ok(None);
+            }
+            return Ok(Some(near));
+        }
+        let mid = if zero_for_one {
+            near - distance / 2
+        } else {
+            near + distance / 2
+        };
+        if within_max(mid)? {
+            near = mid;
+        } else {
+            far = mid;
+        }
+    }
+}
+
+// The zero_for_one flag that trades token_in for token_out on a pool of pool_token_0 and pool_token_1,
+// whichever way round the route names the pair. None if the pool is not of that pair.
+pub fn normalize_direction(
+    pool_token_0: Address,
+    pool_token_1: Address,
+    token_in: Address,
+    token_out: Address,
+) -> Option<bool> {
+    if token_in == pool_token_0 && token_out == pool_token_1 {
+        Some(true)
+    } else if token_in == pool_token_1 && token_out == pool_token_0 {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+// The constant liquidity that would move the price as far as the swap does for the same amounts, i.e.
+// the liquidity of the crossed ranges averaged over the price movement. Derived from the token1 side,
+// whose amount is linear in the sqrt price: amount1 = L * (sqrt_price_a - sqrt_price_b) / Q96.
+pub fn effective_liquidity(
+    pool: &PoolState,
+    zero_for_one: bool,
+    amount_specified: I256,
+    fee: u32,
+) -> Result<f64, UniswapV3MathError> {
+    let result = pool.swap(
+        zero_for_one,
+        amount_specified,
+        default_sqrt_price_limit(zero_for_one),
+        fee,
+    )?;
+    let sqrt_price_before = pool.slot0.sqrt_price;
+    let sqrt_price_after = result.sqrt_price_after;
+    if sqrt_price_after == sqrt_price_before {
+        return Ok(liquidity_to_f64(pool.slot0.liquidity));
+    }
+
+    // the fee is taken on the input and does not move the price
+    let amount_1 = if zero_for_one {
+        result.amount1_delta.unsigned_abs()
+    } else {
+        result.amount1_delta.unsigned_abs() - result.fee_amount
+    };
+    let sqrt_price_delta = if zero_for_one {
+        sqrt_price_before - sqrt_price_after
+    } else {
+        sqrt_price_after - sqrt_price_before
+    };
+    Ok(u256_to_f64(amount_1) * 2f64.powi(96) / u256_to_f64(sqrt_price_delta))
+}
+
+// Fee in hundredths of a bip implied by an observed swap within a single range of liquidity. The price
+// movement fixes the input the pool kept net of the fee, the rest of amount_in is the fee. NaN if
+// amount_out is more than the price movement pays out, i.e. the swap did not happen on this range.
+pub fn implied_fee(
+    amount_in: U256,
+    amount_out: U256,
+    sqrt_price_before: U256,
+    sqrt_price_after: U256,
+    liquidity: u128,
+    zero_for_one: bool,
+) -> Result<f64, UniswapV3MathError> {
+    let (amount_in_less_fee, amount_out_max) = if zero_for_one {
+        (
+            sqrt_price_math::_get_amount_0_delta(
+                sqrt_price_after,
+                sqrt_price_before,
+                liquidity,
+                true,
+            )?,
+            sqrt_price_math::_get_amount_1_delta(
+                sqrt_price_after,
+                sqrt_price_before,
+                liquidity,
+                false,
+            )?,
+        )
+    } else {
+        (
+            sqrt_price_math::_get_amount_1_delta(
+                sqrt_price_before,
+                sqrt_price_after,
+                liquidity,
+                true,
+            )?,
+            sqrt_price_math::_get_amount_0_delta(
+                sqrt_price_before,
+                sqrt_price_after,
+                liquidity,
+                false,
+            )?,
+        )
+    };
+    if amount_out > amount_out_max || amount_in < amount_in_less_fee || amount_in.is_zero() {
+        return Ok(f64::NAN);
+    }
+
+    Ok(u256_to_f64(amount_in - amount_in_less_fee) / u256_to_f64(amount_in) * 1e6)
+}
+
+// The sqrt price after applying (zero_for_one, amount_specified, fee) swaps one after the other at the
+// default price limits, for forecasting without keeping each SwapResult or cloning the pool
+pub fn price_after_swaps(
+    pool: &PoolState,
+    swaps: &[(bool, I256, u32)],
+) -> Result<U256, UniswapV3MathError> {
+    let mut slot0 = pool.slot0.clone();
+    for &(zero_for_one, amount_specified, fee) in swaps {
+        let (result, _) = swap_with_observer(
+            &pool.ticks,
+            &pool.tick_bitmap,
+            pool.tick_spacing,
+            zero_for_one,
+            amount_specified,
+            default_sqrt_price_limit(zero_for_one),
+            &slot0,
+            fee,
+            |_| true,
+        )?;
+        slot0 = Slot0 {
+            sqrt_price: result.sqrt_price_after,
+            liquidity: result.liquidity_after,
+            tick: result.tick_after,
+        };
+    }
+    Ok(slot0.sqrt_price)
+}
+
+// The tick whose spot price equals the price the pool traded at in a swap, i.e. amount out over amount
+// in net of the fee, both in raw token units so that decimals do not matter. Lies between the ticks
+// before and after the swap. slot0_before.tick if the swap did not trade.
+pub fn execution_price_tick(
+    result: &SwapResult,
+    slot0_before: &Slot0,
+) -> Result<i32, UniswapV3MathError> {
+    // the input is on the side of the positive delta
+    let (mut amount_0, mut amount_1) = (
+        result.amount0_delta.unsigned_abs(),
+        result.amount1_delta.unsigned_abs(),
+    );
+    if result.amount0_delta.is_positive() {
+        amount_0 = amount_0.saturating_sub(result.fee_amount);
+    } else {
+        amount_1 = amount_1.saturating_sub(result.fee_amount);
+    }
+    if amount_0.is_zero() || amount_1.is_zero() {
+        return Ok(slot0_before.tick);
+    }
+
+    // sqrt(amount_1 / amount_0) in Q64.96, falling back to Q128 and a 32 bit shift for large prices
+    let sqrt_price = match full_math::mul_div(amount_1, U256::one() << 192, amount_0) {
+        Ok(price_x192) => sqrt_u256(price_x192),
+        Err(_) => sqrt_u256(full_math::mul_div(amount_1, U256::one() << 128, amount_0)?) << 32,
+    };
+    let sqrt_price = sqrt_price
+        .max(tick_math::MIN_SQRT_RATIO)
+        .min(tick_math::MAX_SQRT_RATIO - 1);
+    tick_math::get_tick_at_sqrt_ratio(sqrt_price)
+}
+
+// Amounts of token0 and token1 held by the liquidity of the pool at its current price, rounded down.
+// Each range between two initialized ticks holds token0 above the price and token1 below it.
+pub fn pool_reserves(pool: &PoolState) -> Result<(U256, U256), UniswapV3MathError> {
+    let mut indexes: Vec<i32> = pool.ticks.keys().copied().collect();
+    indexes.sort_unstable();
+
+    let sqrt_price = pool.slot0.sqrt_price;
+    let (mut reserve_0, mut reserve_1) = (U256::zero(), U256::zero());
+    let mut liquidity = 0u128;
+    for range in indexes.windows(2) {
+        liquidity = liquidity_math::add_delta(liquidity, pool.ticks[&range[0]].l_net)?;
+        if liquidity == 0 {
+            continue;
+        }
+        let sqrt_ratio_a = tick_math::get_sqrt_ratio_at_tick(range[0])?;
+        let sqrt_ratio_b = tick_math::get_sqrt_ratio_at_tick(range[1])?;
+        let sqrt_price = sqrt_price.max(sqrt_ratio_a).min(sqrt_ratio_b);
+        reserve_0 +=
+            sqrt_price_math::_get_amount_0_delta(sqrt_price, sqrt_ratio_b, liquidity, false)?;
+        reserve_1 +=
+            sqrt_price_math::_get_amount_1_delta(sqrt_ratio_a, sqrt_price, liquidity, false)?;
+    }
+    Ok((reserve_0, reserve_1))
+}
+
+// Spot price of token0 in token1, adjusted for the decimals, of a full range pool from its reserves.
+// A full range position holds its tokens like a constant product pool, so the price is the ratio of
+// the reserves, up to the remainder past the usable ticks. Infinite without token0.
+pub fn price_from_reserves(reserve0: U256, reserve1: U256, decimals_0: u8, decimals_1: u8) -> f64 {
+    u256_to_f64(reserve1) / u256_to_f64(reserve0)
+        * 10f64.powi(decimals_0 as i32 - decimals_1 as i32)
+}
+
+// Percentages of the value of pool_reserves() held in token0 and in token1, valuing token1 at
+// token1_price_in_token0 (human units). (0, 0) for an empty pool.
+pub fn pool_composition(
+    pool: &PoolState,
+    decimals_0: u8,
+    decimals_1: u8,
+    token1_price_in_token0: f64,
+) -> Result<(f64, f64), UniswapV3MathError> {
+    let (reserve_0, reserve_1) = pool_reserves(pool)?;
+    let value_0 = u256_to_f64(reserve_0) / 10f64.powi(decimals_0 as i32);
+    let value_1 = u256_to_f64(reserve_1) / 10f64.powi(decimals_1 as i32) * token1_price_in_token0;
+    let total = value_0 + value_1;
+    if total == 0f64 {
+        return Ok((0f64, 0f64));
+    }
+    Ok((value_0 / total * 100f64, value_1 / total * 100f64))
+}
+
+// Applies pending (zero_for_one, amount_specified, fee) swaps in the given order, like the next block
+// would, returning the final sqrt price and the result of each swap. Unlike replay_block() the order is
+// chosen by the caller, to compare orderings.
+pub fn simulate_pending(
+    pool: &PoolState,
+    pending_swaps: &[(bool, I256, u32)],
+) -> Result<(U256, Vec<SwapResult>), UniswapV3MathError> {
+    let mut state = MutablePoolState::new(pool.clone());
+    let mut results = Vec::with_capacity(pending_swaps.len());
+    for &(zero_for_one, amount_specified, fee) in pending_swaps {
+        results.push(state.apply_swap(
+            zero_for_one,
+            amount_specified,
+            default_sqrt_price_limit(zero_for_one),
+            fee,
+        )?);
+    }
+    Ok((state.state.slot0.sqrt_price, results))
+}
+
+// returns (bool zeroForOne, uint256 amountIn, uint256 profit)
+// The swap that trades the pool towards external_price (token0 in token1, adjusted for the decimals)
+// for as long as it pays: selling token0 until its marginal output net of the fee drops to the external
+// price, or buying token0 until its marginal cost including the fee rises to it. The profit is the
+// output valued at the external price minus the input, both in the input token. Zero amounts if the
+// pool price is within the fee of the external price.
+pub fn arb_to_external_price(
+    pool: &PoolState,
+    external_price: f64,
+    decimals_0: u8,
+    decimals_1: u8,
+    fee: u32,
+) -> Result<(bool, U256, U256), UniswapV3MathError> {
+    let sqrt_price_external = price_to_sqrt_price_x96(external_price, decimals_0, decimals_1);
+    let raw_price = external_price * 10f64.powi(decimals_1 as i32 - decimals_0 as i32);
+    let kept = 1f64 - fee as f64 / 1e6;
+    let sqrt_price_sell = f64_to_u256((raw_price / kept).sqrt() * 2f64.powi(96));
+    let sqrt_price_buy = f64_to_u256((raw_price * kept).sqrt() * 2f64.powi(96));
+
+    let sqrt_price = pool.slot0.sqrt_price;
+    let (zero_for_one, sqrt_price_limit) = if sqrt_price > sqrt_price_sell {
+        (true, sqrt_price_sell.max(default_sqrt_price_limit(true)))
+    } else if sqrt_price < sqrt_price_buy {
+        (false, sqrt_price_buy.min(default_sqrt_price_limit(false)))
+    } else {
+        return Ok((sqrt_price > sqrt_price_external, U256::zero(), U256::zero()));
+    };
+
+    let result = pool.swap(zero_for_one, I256::MAX, sqrt_price_limit, fee)?;
+    let (amount_in, amount_out) = result.io(zero_for_one);
+    // the output in the input token at the external price
+    let amount_out_value = if zero_for_one {
+        full_math::mul_div(
+            full_math::mul_div(amount_out, sqrt_price_math::Q96, sqrt_price_external)?,
+            sqrt_price_math::Q96,
+            sqrt_price_external,
+        )?
+    } else {
+        full_math::mul_div(
+            full_math::mul_div(amount_out, sqrt_price_external, sqrt_price_math::Q96)?,
+            sqrt_price_external,
+            sqrt_price_math::Q96,
+        )?
+    };
+    Ok((
+        zero_for_one,
+        amount_in,
+        amount_out_value.saturating_sub(amount_in),
+    ))
+}
+
+// returns (bool zeroForOne, uint256 amountIn)
+// The input, fee included, that moves the pool to the tick nearest to target_price (token0 in token1,
+// adjusted for the decimals), found by swapping everything with that tick's sqrt ratio as the limit.
+// (false, 0) if the pool is already at that sqrt ratio.
+pub fn amount_to_external_price(
+    pool: &PoolState,
+    target_price: f64,
+    decimals_0: u8,
+    decimals_1: u8,
+    fee: u32,
+) -> Result<(bool, U256), UniswapV3MathError> {
+    let raw_price = target_price * 10f64.powi(decimals_1 as i32 - decimals_0 as i32);
+    let tick = (raw_price.ln() / 1.0001f64.ln()).round() as i32;
+    // the ratios of the extreme ticks are rejected as limits
+    let tick = tick.clamp(tick_math::MIN_TICK + 1, tick_math::MAX_TICK - 1);
+    let sqrt_price_limit = tick_math::get_sqrt_ratio_at_tick(tick)?;
+
+    let zero_for_one = match direction_for_limit(&pool.slot0, sqrt_price_limit) {
+        Some(zero_for_one) => zero_for_one,
+        None => return Ok((false, U256::zero())),
+    };
+    let result = pool.swap(zero_for_one, I256::MAX, sqrt_price_limit, fee)?;
+    Ok((zero_for_one, result.io(zero_for_one).0))
+}
+
+// Round trip spread in bps of the mid price for trading size raw units of token0: the token1 paid to
+// buy them against the token1 received for selling them. Decimals cancel out of the ratio. Infinite if
+// the pool cannot fill either side in full.
+pub fn bid_ask_spread_bps(
+    pool: &PoolState,
+    size: U256,
+    fee: u32,
+) -> Result<f64, UniswapV3MathError> {
+    if size.is_zero() || size > I256::MAX.into_raw() {
+        return Ok(f64::INFINITY);
+    }
+    let size_signed = I256::from_raw(size);
+    let sell = pool.swap(true, size_signed, default_sqrt_price_limit(true), fee)?;
+    let buy = pool.swap(false, -size_signed, default_sqrt_price_limit(false), fee)?;
+    if sell.amount0_delta != size_signed || buy.amount0_delta != -size_signed {
+        return Ok(f64::INFINITY);
+    }
+
+    let bid = u256_to_f64(sell.amount1_delta.unsigned_abs());
+    let ask = u256_to_f64(buy.amount1_delta.unsigned_abs());
+    Ok((ask - bid) / ((ask + bid) / 2f64) * 10000f64)
+}
+
+// Relative error of the output of f64_swap::swap() against swap() for exact input swaps of each of
+// sizes (raw units of the input token), to find the sizes for which the float path can be trusted.
+// Infinite if only the float swap has output.
+pub fn divergence_sweep(
+    pool: &PoolState,
+    zero_for_one: bool,
+    sizes: &[U256],
+    fee: u32,
+    decimals_0: u8,
+    decimals_1: u8,
+) -> Result<Vec<f64>, UniswapV3MathError> {
+    let token0_decimals_factor = 10f64.powi(decimals_0 as i32);
+    let token1_decimals_factor = 10f64.powi(decimals_1 as i32);
+    let (input_factor, output_factor) = if zero_for_one {
+        (token0_decimals_factor, token1_decimals_factor)
+    } else {
+        (token1_decimals_factor, token0_decimals_factor)
+    };
+    let slot0 = f64_swap::Slot0 {
+        sqrt_price: u256_to_f64(pool.slot0.sqrt_price),
+        liquidity: pool.slot0.liquidity,
+        tick: pool.slot0.tick,
+    };
+    let sqrt_price_limit = default_sqrt_price_limit(zero_for_one);
+
+    let mut errors = Vec::with_capacity(sizes.len());
+    for &size in sizes {
+        let amount_specified = I256::from_raw(size.min(I256::MAX.into_raw()));
+        let exact = pool.swap(zero_for_one, amount_specified, sqrt_price_limit, fee)?;
+        let float = f64_swap::swap(
+            &pool.ticks,
+            &pool.tick_bitmap,
+            pool.tick_spacing,
+            zero_for_one,
+            u256_to_f64(size) / input_factor,
+            u256_to_f64(sqrt_price_limit),
+            &slot0,
+            fee as f64 / 1e6,
+            token0_decimals_factor,
+            token1_decimals_factor,
+        )?;
+
+        let exact_out = u256_to_f64(exact.io(zero_for_one).1);
+        let float_out = if zero_for_one {
+            float.amount1_delta
+        } else {
+            float.amount0_delta
+        }
+        .abs()
+            * output_factor;
+        errors.push(if exact_out == 0f64 {
+            if float_out == 0f64 {
+                0f64
+            } else {
+                f64::INFINITY
+            }
+        } else {
+            (float_out - exact_out).abs() / exact_out
+        });
+    }
+    Ok(errors)
+}
+
+// The pool among candidates giving the best swap at the same fee: the most output for an exact input,
+// the least input for an exact output. Ties go to the first pool. None for no candidates.
+pub fn best_pool(
+    pools: &[PoolState],
+    zero_for_one: bool,
+    amount_specified: I256,
+    fee: u32,
+) -> Result<Option<(usize, SwapResult)>, UniswapV3MathError> {
+    let exact_input = amount_specified.is_positive();
+    let mut best: Option<(usize, SwapResult)> = None;
+    for (index, pool) in pools.iter().enumerate() {
+        let result = pool.swap(
+            zero_for_one,
+            amount_specified,
+            default_sqrt_price_limit(zero_for_one),
+            fee,
+        )?;
+        let better = match &best {
+            None => true,
+            Some((_, best)) => {
+                let (amount_in, amount_out) = result.io(zero_for_one);
+                let (best_in, best_out) = best.io(zero_for_one);
+                if exact_input {
+                    amount_out > best_out
+                } else {
+                    // a pool that cannot fill the output is only better if it pays out more
+                    amount_out > best_out || (amount_out == best_out && amount_in < best_in)
+                }
+            }
+        };
+        if better {
+            best = Some((index, result));
+        }
+    }
+    Ok(best)
+}
+
+// Minimum relative price difference between two pools, in bps, for a round trip through both to
+// beat their fees. Buying in one pool and selling in the other keeps (1 - fee_a) * (1 - fee_b) of
+// the value, so the spread has to make up for the rest. Fees are in hundredths of a bip.
+pub fn min_arb_spread_bps(fee_a: u32, fee_b: u32) -> f64 {
+    let kept = (1f64 - fee_a as f64 / 1e6) * (1f64 - fee_b as f64 / 1e6);
+    (1f64 / kept - 1f64) * 10000f64
+}
+
+// Describes the first amount delta of a quote that deviates from the actual swap by more than the tolerance
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct QuoteMismatch {
+    // 0 for amount0_delta, 1 for amount1_delta
+    pub token: u8,
+    pub quoted: I256,
+    pub actual: I256,
+    // U256::MAX if the actual delta is zero but the quoted one is not
+    pub deviation_bps: U256,
+}
+
+// Checks that both amount deltas of a quote are within tolerance_bps of an actual swap result
+pub fn verify_quote(
+    quoted: &SwapResult,
+    actual: &SwapResult,
+    tolerance_bps: u32,
+) -> Result<(), QuoteMismatch> {
+    let deltas = [
+        (quoted.amount0_delta, actual.amount0_delta),
+        (quoted.amount1_delta, actual.amount1_delta),
+    ];
+    for (token, (quoted, actual)) in deltas.into_iter().enumerate() {
+        // |quoted - actual| without the I256 subtraction, which overflows for deltas of opposite sign
+        // near the bounds
+        let (quoted_abs, actual_abs) = (quoted.unsigned_abs(), actual.unsigned_abs());
+        let diff = if quoted.is_negative() == actual.is_negative() {
+            quoted_abs.max(actual_abs) - quoted_abs.min(actual_abs)
+        } else {
+            quoted_abs.saturating_add(actual_abs)
+        };
+        if diff.full_mul(U256::from(10000)) > actual_abs.full_mul(U256::from(tolerance_bps)) {
+            let deviation_bps = if actual_abs.is_zero() {
+                U256::MAX
+            } else {
+                diff.saturating_mul(U256::from(10000)) / actual_abs
+            };
+            return Err(QuoteMismatch {
+                token: token as u8,
+                quoted,
+                actual,
+                deviation_bps,
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::cell::RefCell;
+
+    use ethers::{
+        abi::{encode, Token},
+        contract::EthEvent,
+        types::{Address, Log, H256, I256, U256, U512},
+    };
+    use hashbrown::HashMap;
+
+    use crate::{
+        abi::SwapFilter,
+        error::UniswapV3MathError,
+        tick_bitmap::flip_tick,
+        tick_math::{get_sqrt_ratio_at_tick, get_tick_at_sqrt_ratio, MAX_TICK, MIN_TICK},
+        utils::{tick_to_price, u256_to_f64},
+    };
+
+    use super::{
+        active_range_price_width, amount_to_external_price, arb_to_external_price,
+        average_execution_tick, best_pool, bid_ask_spread_bps, complexity,
+        default_sqrt_price_limit, depth_at_price_move, direction_for_limit, divergence_sweep,
+        effective_liquidity, equalizing_swap, execution_by_range, execution_price_tick,
+        fee_equals_impact_size, fee_growth_over_swaps, implied_fee, is_limit_binding,
+        limit_for_avg_price, marginal_output, max_amount_in_current_tick, min_arb_spread_bps,
+        min_received, normalize_direction, output_bounds, pool_composition, pool_reserves,
+        price_after_swaps, price_at_fraction, price_from_reserves, prices,
+        reconstruct_active_liquidity, replay_block, simulate_pending, swap, swap_budgeted,
+        swap_full_trace, swap_traced, swap_with_protocol_fee, swap_with_tick_list,
+        swap_with_word_limit, total_fee_growth, validate_pool, verify_quote, vwap_tick,
+        BoundedSwapError, MutablePoolState, PoolSnapshot, PoolState, QuoteMismatch, Slot0,
+        SwapComplexity, SwapResult, TickInfo,
+    };
+
+    // Builds a pool at `tick` out of (tick_lower, tick_upper, liquidity) positions
+    pub fn pool_with_positions(
+        positions: &[(i32, i32, u128)],
+        tick_spacing: i32,
+        tick: i32,
+    ) -> PoolState {
+        let mut ticks: HashMap<i32, TickInfo> = HashMap::new();
+        let mut tick_bitmap: HashMap<i16, U256> = HashMap::new();
+        let mut liquidity = 0;
+
+        for &(tick_lower, tick_upper, amount) in positions {
+            for (index, l_net) in [
+                (tick_lower, amount as i128),
+                (tick_upper, -(amount as i128)),
+            ] {
+                let info = ticks.entry(index).or_insert_with(|| {
+                    flip_tick(&mut tick_bitmap, index, tick_spacing).unwrap();
+                    TickInfo {
+                        index,
+                        l_gross: 0,
+                        l_net: 0,
+                    }
+                });
+                info.l_gross += amount;
+                info.l_net += l_net;
+            }
+            if tick_lower <= tick && tick < tick_upper {
+                liquidity += amount;
+            }
+        }
+
+        PoolState {
+            ticks,
+            tick_bitmap,
+            tick_spacing,
+            slot0: Slot0 {
+                sqrt_price: get_sqrt_ratio_at_tick(tick).unwrap(),
+                liquidity,
+                tick,
+            },
+        }
+    }
+
+    pub fn test_pool() -> PoolState {
+        pool_with_positions(
+            &[(-600, 600, 1e18 as u128), (-1200, -300, 5e17 as u128)],
+            60,
+            0,
+        )
+    }
+
+    fn swap_log(result: &SwapResult) -> Log {
+        Log {
+            topics: vec![SwapFilter::signature(), H256::zero(), H256::zero()],
+            data: encode(&[
+                Token::Int(result.amount0_delta.into_raw()),
+                Token::Int(result.amount1_delta.into_raw()),
+                Token::Uint(result.sqrt_price_after),
+                Token::Uint(U256::from(result.liquidity_after)),
+                Token::Int(I256::from(result.tick_after).into_raw()),
+            ])
+            .into(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_replay_block() {
+        //produce the logs of two swaps, the first one crossing tick -300
+        let mut pool = MutablePoolState::new(test_pool());
+        let first = pool
+            .apply_swap(
+                true,
+                I256::from_dec_str("30000000000000000").unwrap(),
+                default_sqrt_price_limit(true),
+                3000,
+            )
+            .unwrap();
+        let second = pool
+            .apply_swap(
+                false,
+                I256::from_dec_str("10000000000000000").unwrap(),
+                default_sqrt_price_limit(false),
+                3000,
+            )
+            .unwrap();
+        assert!(first.tick_after < -300);
+        assert_eq!(first.liquidity_after, 15e17 as u128);
+
+        let mut replayed = MutablePoolState::new(test_pool());
+        let results =
+            replay_block(&mut replayed, 3000, &[swap_log(&first), swap_log(&second)]).unwrap();
+
+        assert_eq!(results.len(), 2);
+        for (result, expected) in results.iter().zip([&first, &second]) {
+            assert_eq!(result.sqrt_price_after, expected.sqrt_price_after);
+            assert_eq!(result.tick_after, expected.tick_after);
+            assert_eq!(result.liquidity_after, expected.liquidity_after);
+        }
+        //the output side only depends on the price movement
+        assert_eq!(results[0].amount1_delta, first.amount1_delta);
+        assert_eq!(results[1].amount0_delta, second.amount0_delta);
+        assert_eq!(replayed.state.slot0.sqrt_price, pool.state.slot0.sqrt_price);
+
+        //logs of other events are rejected
+        let mut log = swap_log(&first);
+        log.topics[0] = H256::zero();
+        assert!(replay_block(&mut replayed, 3000, &[log]).is_err());
+    }
+
+    #[test]
+    fn test_verify_quote() {
+        let actual = SwapResult {
+            amount0_delta: I256::from(1000000),
+            amount1_delta: I256::from(-2000000),
+            ..Default::default()
+        };
+
+        //exact quote
+        assert!(verify_quote(&actual.clone(), &actual, 0).is_ok());
+
+        //quote within tolerance
+        let quoted = SwapResult {
+            amount0_delta: I256::from(1000000),
+            amount1_delta: I256::from(-2000200),
+            ..Default::default()
+        };
+        assert!(verify_quote(&quoted, &actual, 1).is_ok());
+
+        //quote outside of tolerance
+        let quoted = SwapResult {
+            amount0_delta: I256::from(1000000),
+            amount1_delta: I256::from(-1998000),
+            ..Default::default()
+        };
+        assert_eq!(
+            verify_quote(&quoted, &actual, 5).unwrap_err(),
+            QuoteMismatch {
+                token: 1,
+                quoted: I256::from(-1998000),
+                actual: I256::from(-2000000),
+                deviation_bps: U256::from(10),
+            }
+        );
+
+        //a quote for a swap that did not happen
+        let quoted = SwapResult {
+            amount0_delta: I256::from(1),
+            ..Default::default()
+        };
+        let mismatch = verify_quote(&quoted, &SwapResult::default(), 10000).unwrap_err();
+        assert_eq!(mismatch.token, 0);
+        assert_eq!(mismatch.deviation_bps, U256::MAX);
+    }
+
+    #[test]
+    fn test_effective_fee_bps() {
+        let full_range = (MIN_TICK / 60 * 60, MAX_TICK / 60 * 60);
+        let pool = pool_with_positions(&[(full_range.0, full_range.1, 1e21 as u128)], 60, 0);
+
+        for (zero_for_one, fee) in [(true, 3000), (false, 3000), (true, 500), (false, 10000)] {
+            let result = pool
+                .swap(
+                    zero_for_one,
+                    I256::from_dec_str("1000000000000000000").unwrap(),
+                    default_sqrt_price_limit(zero_for_one),
+                    fee,
+                )
+                .unwrap();
+            assert!((result.effective_fee_bps() - fee as f64 / 100f64).abs() < 1e-6);
+        }
+
+        //nothing swapped, nothing paid
+        assert_eq!(SwapResult::default().effective_fee_bps(), 0f64);
+    }
+
+    #[test]
+    fn test_active_range_price_width() {
+        //initialized ticks below and above tick 0 are -300 and 600
+        let width = active_range_price_width(&test_pool(), 18, 6).unwrap();
+        let expected = (1.0001f64.powi(600) - 1.0001f64.powi(-300)) * 1e12;
+        assert!((width - expected).abs() / expected < 1e-12);
+
+        //no initialized tick above the current one
+        let pool = pool_with_positions(&[(-600, 600, 1e18 as u128)], 60, 900);
+        let width = active_range_price_width(&pool, 18, 18).unwrap();
+        assert_eq!(width, 1.0001f64.powi(MAX_TICK) - 1.0001f64.powi(600));
+    }
+
+    #[test]
+    fn test_tick_after_stopping_mid_word() {
+        //ticks -300 and -600 share a bitmap word, the swap crosses -300 and stops before -600
+        let pool = test_pool();
+
+        for amount_specified in ["30000000000000000", "-25000000000000000"] {
+            let result = pool
+                .swap(
+                    true,
+                    I256::from_dec_str(amount_specified).unwrap(),
+                    default_sqrt_price_limit(true),
+                    3000,
+                )
+                .unwrap();
+
+            assert!(result.tick_after < -300 && result.tick_after > -600);
+            assert_eq!(
+                result.tick_after,
+                get_tick_at_sqrt_ratio(result.sqrt_price_after).unwrap()
+            );
+            //getTickAtSqrtRatio rounds down, the price lies within [tick, tick + 1)
+            assert!(get_sqrt_ratio_at_tick(result.tick_after).unwrap() <= result.sqrt_price_after);
+            assert!(
+                get_sqrt_ratio_at_tick(result.tick_after + 1).unwrap() > result.sqrt_price_after
+            );
+            assert_eq!(result.liquidity_after, 15e17 as u128);
+        }
+    }
+
+    #[test]
+    fn test_min_received() {
+        let pool = test_pool();
+        let amount_in = U256::from_dec_str("30000000000000000").unwrap();
+
+        let quote = pool
+            .swap(
+                true,
+                I256::from_raw(amount_in),
+                default_sqrt_price_limit(true),
+                3000,
+            )
+            .unwrap();
+        let amount_out = (-quote.amount1_delta).into_raw();
+        assert_eq!(amount_out, U256::from_dec_str("29109487006785507").unwrap());
+
+        //no slippage, the full quote
+        assert_eq!(
+            min_received(&pool, true, amount_in, 0, 3000).unwrap(),
+            amount_out
+        );
+
+        //0.5% slippage
+        assert_eq!(
+            min_received(&pool, true, amount_in, 50, 3000).unwrap(),
+            amount_out * 9950 / 10000
+        );
+
+        //the other direction
+        let quote = pool
+            .swap(
+                false,
+                I256::from_raw(amount_in),
+                default_sqrt_price_limit(false),
+                3000,
+            )
+            .unwrap();
+        assert_eq!(
+            min_received(&pool, false, amount_in, 100, 3000).unwrap(),
+            (-quote.amount0_delta).into_raw() * 9900 / 10000
+        );
+    }
+
+    #[test]
+    fn test_min_arb_spread_bps() {
+        assert_eq!(min_arb_spread_bps(0, 0), 0f64);
+        assert!((min_arb_spread_bps(500, 500) - 10.0075050).abs() < 1e-6);
+        assert!((min_arb_spread_bps(3000, 500) - 35.1078247).abs() < 1e-6);
+        assert!((min_arb_spread_bps(3000, 3000) - 60.2710841).abs() < 1e-6);
+        assert!((min_arb_spread_bps(10000, 3000) - 131.4043140).abs() < 1e-6);
+        //symmetric in the two pools
+        assert_eq!(min_arb_spread_bps(500, 3000), min_arb_spread_bps(3000, 500));
+    }
+
+    #[test]
+    fn test_swap_traced() {
+        let pool = test_pool();
+        let (result, trace) = swap_traced(
+            &pool.ticks,
+            &pool.tick_bitmap,
+            pool.tick_spacing,
+            true,
+            I256::from_dec_str("30000000000000000").unwrap(),
+            default_sqrt_price_limit(true),
+            &pool.slot0,
+            3000,
+        )
+        .unwrap();
+
+        //one step up to tick 0, one to tick -300 and one inside [-600, -300)
+        assert_eq!(trace.len(), 3);
+        assert_eq!(trace[0].sqrt_price_start_x96, pool.slot0.sqrt_price);
+        assert_eq!(trace[1].tick_next, -300);
+        assert!(trace[1].initialized);
+        assert_eq!(trace[1].liquidity, 1e18 as u128);
+        assert_eq!(trace[2].liquidity, 15e17 as u128);
+        for steps in trace.windows(2) {
+            assert_eq!(steps[0].sqrt_price_end_x96, steps[1].sqrt_price_start_x96);
+            assert_eq!(steps[0].tick_end, steps[1].tick_start);
+        }
+
+        let last = trace.last().unwrap();
+        assert_eq!(last.sqrt_price_end_x96, result.sqrt_price_after);
+        assert_eq!(last.tick_end, result.tick_after);
+
+        let amount_in = trace.iter().fold(U256::zero(), |sum, step| {
+            sum + step.amount_in + step.fee_amount
+        });
+        let amount_out = trace
+            .iter()
+            .fold(U256::zero(), |sum, step| sum + step.amount_out);
+        assert_eq!(I256::from_raw(amount_in), result.amount0_delta);
+        assert_eq!(-I256::from_raw(amount_out), result.amount1_delta);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_trace_to_json() {
+        let pool = test_pool();
+        let (_, trace) = swap_traced(
+            &pool.ticks,
+            &pool.tick_bitmap,
+            pool.tick_spacing,
+            true,
+            I256::from_dec_str("30000000000000000").unwrap(),
+            default_sqrt_price_limit(true),
+            &pool.slot0,
+            3000,
+        )
+        .unwrap();
+
+        let json = super::trace_to_json(&trace);
+        let decoded: Vec<super::SwapStepTrace> = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded, trace);
+    }
+
+    #[test]
+    fn test_swap_from_uninitialized_tick() {
+        //none of the starting ticks is a position boundary, the closest initialized ticks are -300 and 600
+        for (tick, tick_after_zero_for_one, tick_after_one_for_zero) in
+            [(0, -199, 198), (30, -169, 228), (-30, -229, 168)]
+        {
+            let pool = pool_with_positions(
+                &[(-600, 600, 1e18 as u128), (-1200, -300, 5e17 as u128)],
+                60,
+                tick,
+            );
+            assert!(!pool.ticks.contains_key(&tick));
+
+            for (zero_for_one, tick_after) in [
+                (true, tick_after_zero_for_one),
+                (false, tick_after_one_for_zero),
+            ] {
+                let (result, trace) = swap_traced(
+                    &pool.ticks,
+                    &pool.tick_bitmap,
+                    pool.tick_spacing,
+                    zero_for_one,
+                    I256::from_dec_str("10000000000000000").unwrap(),
+                    default_sqrt_price_limit(zero_for_one),
+                    &pool.slot0,
+                    3000,
+                )
+                .unwrap();
+
+                //the scan starts from the active tick and finds the closest initialized tick
+                let first_initialized = trace.iter().find(|step| step.initialized).unwrap();
+                assert_eq!(
+                    first_initialized.tick_next,
+                    if zero_for_one { -300 } else { 600 }
+                );
+                //no liquidity is picked up before reaching it
+                assert!(trace.iter().all(|step| step.liquidity == 1e18 as u128));
+                assert_eq!(result.tick_after, tick_after);
+                assert_eq!(result.liquidity_after, 1e18 as u128);
+            }
+        }
+    }
+
+    #[test]
+    fn test_price_range() {
+        let pool = test_pool();
+        let start = pool.slot0.sqrt_price;
+
+        for zero_for_one in [true, false] {
+            let (result, trace) = swap_traced(
+                &pool.ticks,
+                &pool.tick_bitmap,
+                pool.tick_spacing,
+                zero_for_one,
+                I256::from_dec_str("30000000000000000").unwrap(),
+                default_sqrt_price_limit(zero_for_one),
+                &pool.slot0,
+                3000,
+            )
+            .unwrap();
+
+            //the price only moves in the swap direction, the range is spanned by the endpoints
+            for step in &trace {
+                if zero_for_one {
+                    assert!(step.sqrt_price_end_x96 <= step.sqrt_price_start_x96);
+                } else {
+                    assert!(step.sqrt_price_end_x96 >= step.sqrt_price_start_x96);
+                }
+            }
+            let expected = if zero_for_one {
+                (result.sqrt_price_after, start)
+            } else {
+                (start, result.sqrt_price_after)
+            };
+            assert_eq!(result.price_range(), expected);
+        }
+    }
+
+    #[test]
+    fn test_depth_at_price_move() {
+        let pool = test_pool();
+
+        //2% stays within [-300, 600)
+        let (buy_depth, sell_depth) = depth_at_price_move(&pool, 200, 3000).unwrap();
+        assert_eq!(buy_depth, U256::from_dec_str("9980435141632695").unwrap());
+        assert_eq!(sell_depth, U256::from_dec_str("10183093833711886").unwrap());
+
+        //5% down crosses tick -300 and picks up the second position
+        let (buy_depth, sell_depth) = depth_at_price_move(&pool, 500, 3000).unwrap();
+        assert_eq!(buy_depth, U256::from_dec_str("24769384750210471").unwrap());
+        assert_eq!(sell_depth, U256::from_dec_str("31505894144184786").unwrap());
+
+        //moving the price further than the liquidity reaches takes all of it
+        let (buy_depth, sell_depth) = depth_at_price_move(&pool, 20000, 3000).unwrap();
+        assert_eq!(buy_depth, U256::from_dec_str("30544622242640681").unwrap());
+        assert_eq!(sell_depth, U256::from_dec_str("53975443667360423").unwrap());
+
+        //not moving the price takes nothing
+        assert_eq!(
+            depth_at_price_move(&pool, 0, 3000).unwrap(),
+            (U256::zero(), U256::zero())
+        );
+    }
+
+    #[test]
+    fn test_advance_time() {
+        let mut pool = MutablePoolState::new(test_pool());
+
+        //100 seconds at tick 0 with 1e18 liquidity
+        pool.advance_time(100);
+        assert_eq!(pool.tick_cumulative, 0);
+        let first_period = (U256::from(100) << 128) / U256::from(1e18 as u128);
+        assert_eq!(pool.seconds_per_liquidity_cumulative_x128, first_period);
+
+        //then 50 seconds below tick -300 with 1.5e18 liquidity
+        let result = pool
+            .apply_swap(
+                true,
+                I256::from_dec_str("30000000000000000").unwrap(),
+                default_sqrt_price_limit(true),
+                3000,
+            )
+            .unwrap();
+        pool.advance_time(50);
+        assert_eq!(pool.tick_cumulative, result.tick_after as i64 * 50);
+        assert_eq!(
+            pool.seconds_per_liquidity_cumulative_x128,
+            first_period + (U256::from(50) << 128) / U256::from(15e17 as u128)
+        );
+    }
+
+    #[test]
+    fn test_equalizing_swap() {
+        let pool_a = test_pool();
+
+        for (tick_b, zero_for_one, amount_in) in [
+            (-400, true, "22812766474345331"),
+            (250, false, "12615665718643443"),
+        ] {
+            let pool_b = pool_with_positions(&[(-600, 600, 1e18 as u128)], 60, tick_b);
+            let (direction, amount) = equalizing_swap(&pool_a, &pool_b, 3000).unwrap();
+            assert_eq!(direction, zero_for_one);
+            assert_eq!(amount, U256::from_dec_str(amount_in).unwrap());
+
+            //the swap is exhausted exactly at the price of pool_b
+            let result = pool_a
+                .swap(
+                    direction,
+                    I256::from_raw(amount),
+                    pool_b.slot0.sqrt_price,
+                    3000,
+                )
+                .unwrap();
+            assert_eq!(result.sqrt_price_after, pool_b.slot0.sqrt_price);
+            assert_eq!(result.tick_after, tick_b);
+        }
+
+        //pools at the same price
+        let (_, amount) = equalizing_swap(&pool_a, &test_pool(), 3000).unwrap();
+        assert!(amount.is_zero());
+    }
+
+    #[test]
+    fn test_is_limit_binding() {
+        let pool = test_pool();
+        //both swaps end around tick -494 without a limit
+        for amount_specified in ["30000000000000000", "-29109487006785507"] {
+            let amount_specified = I256::from_dec_str(amount_specified).unwrap();
+
+            //limits short of where the swap ends
+            for tick in [-100, -300, -493] {
+                let sqrt_price_limit = get_sqrt_ratio_at_tick(tick).unwrap();
+                assert!(
+                    is_limit_binding(&pool, true, amount_specified, sqrt_price_limit, 3000)
+                        .unwrap()
+                );
+            }
+
+            //limits beyond it
+            for sqrt_price_limit in [
+                get_sqrt_ratio_at_tick(-495).unwrap(),
+                default_sqrt_price_limit(true),
+            ] {
+                assert!(
+                    !is_limit_binding(&pool, true, amount_specified, sqrt_price_limit, 3000)
+                        .unwrap()
+                );
+            }
+        }
+
+        //more than all the liquidity: stopped by the end of it at the default limit, by a given one
+        for zero_for_one in [true, false] {
+            assert!(!is_limit_binding(
+                &pool,
+                zero_for_one,
+                I256::MAX,
+                default_sqrt_price_limit(zero_for_one),
+                3000
+            )
+            .unwrap());
+        }
+        assert!(is_limit_binding(
+            &pool,
+            true,
+            I256::MAX,
+            get_sqrt_ratio_at_tick(-100).unwrap(),
+            3000
+        )
+        .unwrap());
+    }
+
+    #[test]
+    fn test_swap_with_word_limit() {
+        //a single wide position, the words between tick 0 and -60000 are empty
+        let pool = pool_with_positions(&[(-60000, 60000, 1e18 as u128)], 10, 0);
+        let amount_specified = I256::from_dec_str("1000000000000000000").unwrap();
+        let (expected, trace) = swap_traced(
+            &pool.ticks,
+            &pool.tick_bitmap,
+            pool.tick_spacing,
+            true,
+            amount_specified,
+            default_sqrt_price_limit(true),
+            &pool.slot0,
+            3000,
+        )
+        .unwrap();
+        //tick 0 and the start of six more words towards tick -13834
+        assert_eq!(trace.len(), 7);
+        assert!(trace.iter().all(|step| !step.initialized));
+
+        let swap_with_limit = |max_word_reads| {
+            swap_with_word_limit(
+                &pool.ticks,
+                &pool.tick_bitmap,
+                pool.tick_spacing,
+                true,
+                amount_specified,
+                default_sqrt_price_limit(true),
+                &pool.slot0,
+                3000,
+                max_word_reads,
+            )
+        };
+
+        //enough reads
+        let result = swap_with_limit(trace.len()).unwrap();
+        assert_eq!(result.sqrt_price_after, expected.sqrt_price_after);
+        assert_eq!(result.amount1_delta, expected.amount1_delta);
+
+        //one read short, the partial swap stops where the last allowed step ended
+        match swap_with_limit(trace.len() - 1).unwrap_err() {
+            BoundedSwapError::WordReadLimitExceeded(partial) => {
+                let last = &trace[trace.len() - 2];
+                assert_eq!(partial.sqrt_price_after, last.sqrt_price_end_x96);
+                assert_eq!(partial.tick_after, last.tick_end);
+                assert!(partial.amount0_delta < amount_specified);
+            }
+            err => panic!("unexpected error {:?}", err),
+        }
+
+        //no reads at all
+        match swap_with_limit(0).unwrap_err() {
+            BoundedSwapError::WordReadLimitExceeded(partial) => {
+                assert_eq!(partial.sqrt_price_after, pool.slot0.sqrt_price);
+                assert!(partial.amount0_delta.is_zero());
+            }
+            err => panic!("unexpected error {:?}", err),
+        }
+    }
+
+    #[test]
+    fn test_stale_tick_is_not_crossed() {
+        let expected = test_pool()
+            .swap(
+                true,
+                I256::from_dec_str("30000000000000000").unwrap(),
+                default_sqrt_price_limit(true),
+                3000,
+            )
+            .unwrap();
+
+        //ticks knows about -120 and -420 but the bitmap marks neither as initialized
+        let mut pool = test_pool();
+        for index in [-120, -420] {
+            pool.ticks.insert(
+                index,
+                TickInfo {
+                    index,
+                    l_gross: 1e18 as u128,
+                    l_net: 1e18 as i128,
+                },
+            );
+        }
+        let (result, trace) = swap_traced(
+            &pool.ticks,
+            &pool.tick_bitmap,
+            pool.tick_spacing,
+            true,
+            I256::from_dec_str("30000000000000000").unwrap(),
+            default_sqrt_price_limit(true),
+            &pool.slot0,
+            3000,
+        )
+        .unwrap();
+
+        assert!(trace
+            .iter()
+            .all(|step| step.tick_next != -120 && step.tick_next != -420));
+        assert_eq!(result.liquidity_after, 15e17 as u128);
+        assert_eq!(result.sqrt_price_after, expected.sqrt_price_after);
+        assert_eq!(result.amount1_delta, expected.amount1_delta);
+    }
+
+    #[test]
+    fn test_price_at_fraction() {
+        let pool = test_pool();
+
+        for amount_specified in ["30000000000000000", "-25000000000000000"] {
+            let amount_specified = I256::from_dec_str(amount_specified).unwrap();
+            let swap_amount = |amount| {
+                pool.swap(true, amount, default_sqrt_price_limit(true), 3000)
+                    .unwrap()
+                    .sqrt_price_after
+            };
+
+            //halfway through the swap
+            assert_eq!(
+                price_at_fraction(&pool, true, amount_specified, 3000, 0.5).unwrap(),
+                swap_amount(amount_specified / I256::from(2))
+            );
+            //all of it and none of it
+            assert_eq!(
+                price_at_fraction(&pool, true, amount_specified, 3000, 1f64).unwrap(),
+                swap_amount(amount_specified)
+            );
+            assert_eq!(
+                price_at_fraction(&pool, true, amount_specified, 3000, 0f64).unwrap(),
+                pool.slot0.sqrt_price
+            );
+        }
+    }
+
+    #[test]
+    fn test_marginal_output() {
+        //off tick 0, where the price is 1
+        let pool = pool_with_positions(&[(-600, 600, 1e18 as u128)], 60, 250);
+        let amount_in = 1000000000i64;
+
+        for zero_for_one in [true, false] {
+            let result = pool
+                .swap(
+                    zero_for_one,
+                    I256::from(amount_in),
+                    default_sqrt_price_limit(zero_for_one),
+                    3000,
+                )
+                .unwrap();
+            let amount_out = if zero_for_one {
+                -result.amount1_delta
+            } else {
+                -result.amount0_delta
+            };
+            let actual = amount_out.low_i128() as f64 / amount_in as f64;
+            let marginal = marginal_output(&pool, zero_for_one, 3000);
+            assert!((actual - marginal).abs() / marginal < 1e-6);
+        }
+        assert!(
+            (marginal_output(&pool, true, 0) * marginal_output(&pool, false, 0) - 1f64).abs()
+                < 1e-12
+        );
+    }
+
+    #[test]
+    fn test_reconstruct_active_liquidity() {
+        let positions = [
+            (-600, 600, 1e18 as u128),
+            (-1200, -300, 5e17 as u128),
+            (-300, 1200, 2e17 as u128),
+        ];
+        for tick in [-1500, -1200, -700, -300, -1, 0, 599, 600, 1199, 1200] {
+            let pool = pool_with_positions(&positions, 60, tick);
+            assert_eq!(
+                reconstruct_active_liquidity(&pool.ticks, tick).unwrap(),
+                pool.slot0.liquidity
+            );
+        }
+        let pool = pool_with_positions(&positions, 60, 0);
+        assert_eq!(
+            reconstruct_active_liquidity(&pool.ticks, -300).unwrap(),
+            12e17 as u128
+        );
+
+        //after a swap the active liquidity follows the crossed ticks
+        let result = pool
+            .swap(
+                true,
+                I256::from_dec_str("30000000000000000").unwrap(),
+                default_sqrt_price_limit(true),
+                3000,
+            )
+            .unwrap();
+        assert_eq!(
+            reconstruct_active_liquidity(&pool.ticks, result.tick_after).unwrap(),
+            result.liquidity_after
+        );
+
+        //a tick removing more liquidity than was added is inconsistent
+        let mut ticks = pool.ticks.clone();
+        ticks.get_mut(&600).unwrap().l_net = -(2e18 as i128);
+        assert!(reconstruct_active_liquidity(&ticks, 600).is_err());
+    }
+
+    #[test]
+    fn test_io() {
+        let pool = test_pool();
+        let amount_in = U256::from_dec_str("30000000000000000").unwrap();
+        let amount_out = U256::from_dec_str("20000000000000000").unwrap();
+
+        for zero_for_one in [true, false] {
+            //exact input
+            let result = pool
+                .swap(
+                    zero_for_one,
+                    I256::from_raw(amount_in),
+                    default_sqrt_price_limit(zero_for_one),
+                    3000,
+                )
+                .unwrap();
+            let (io_in, io_out) = result.io(zero_for_one);
+            assert_eq!(io_in, amount_in);
+            assert!(!io_out.is_zero() && io_out < amount_in);
+
+            //exact output
+            let result = pool
+                .swap(
+                    zero_for_one,
+                    -I256::from_raw(amount_out),
+                    default_sqrt_price_limit(zero_for_one),
+                    3000,
+                )
+                .unwrap();
+            let (io_in, io_out) = result.io(zero_for_one);
+            assert_eq!(io_out, amount_out);
+            assert!(io_in > amount_out);
+
+            //the magnitudes of the signed deltas
+            let (delta_in, delta_out) = if zero_for_one {
+                (result.amount0_delta, result.amount1_delta)
+            } else {
+                (result.amount1_delta, result.amount0_delta)
+            };
+            assert!(delta_in.is_positive() && delta_out.is_negative());
+            assert_eq!(I256::from_raw(io_in), delta_in);
+            assert_eq!(-I256::from_raw(io_out), delta_out);
+        }
+    }
+
+    #[test]
+    fn test_swap_drains_pool_to_price_extreme() {
+        let pool = test_pool();
+        let amount_specified = I256::from_dec_str("1000000000000000000000000000000").unwrap();
+
+        for (zero_for_one, tick_after, amount_in) in [
+            (true, MIN_TICK, "53975443667360423"),
+            (false, MAX_TICK - 1, "30544622242640681"),
+        ] {
+            let result = pool
+                .swap(
+                    zero_for_one,
+                    amount_specified,
+                    default_sqrt_price_limit(zero_for_one),
+                    3000,
+                )
+                .unwrap();
+
+            //all liquidity is used up and the swap stops at the most extreme price it accepts
+            assert_eq!(
+                result.sqrt_price_after,
+                default_sqrt_price_limit(zero_for_one)
+            );
+            assert_eq!(result.tick_after, tick_after);
+            assert_eq!(result.liquidity_after, 0);
+            assert_eq!(
+                result.io(zero_for_one).0,
+                U256::from_dec_str(amount_in).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_effective_liquidity() {
+        let pool = test_pool();
+
+        //within [-300, 600) only the nominal liquidity is seen
+        for zero_for_one in [true, false] {
+            let liquidity = effective_liquidity(
+                &pool,
+                zero_for_one,
+                I256::from_dec_str("10000000000000000").unwrap(),
+                3000,
+            )
+            .unwrap();
+            assert!((liquidity / 1e18 - 1f64).abs() < 1e-9);
+        }
+
+        //crossing -300 averages 1e18 and 1.5e18
+        let liquidity = effective_liquidity(
+            &pool,
+            true,
+            I256::from_dec_str("30000000000000000").unwrap(),
+            3000,
+        )
+        .unwrap();
+        assert!(liquidity > 1e18 && liquidity < 15e17);
+
+        //a swap that does not move the price
+        let liquidity = effective_liquidity(&pool, true, I256::zero(), 3000).unwrap();
+        assert_eq!(liquidity, 1e18);
+    }
+
+    #[test]
+    fn test_swap_output_never_exceeds_input_value() {
+        //xorshift64, deterministic so failures can be reproduced
+        let mut seed = 0x2545f4914f6cdd1du64;
+        let mut next = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            seed
+        };
+        let fees = [100, 500, 3000, 10000];
+
+        for _ in 0..256 {
+            let tick = (next() % 2001) as i32 - 1000;
+            let positions: Vec<(i32, i32, u128)> = (0..1 + next() % 3)
+                .map(|_| {
+                    let tick_lower = (tick.div_euclid(60) - 1 - (next() % 20) as i32) * 60;
+                    let tick_upper = (tick.div_euclid(60) + 1 + (next() % 20) as i32) * 60;
+                    let liquidity = 1e17 as u128 + (next() % 1e19 as u64) as u128;
+                    (tick_lower, tick_upper, liquidity)
+                })
+                .collect();
+            let zero_for_one = next() % 2 == 0;
+            let mut amount_specified = I256::from(1e12 as i64 + (next() % 1e17 as u64) as i64);
+            if next() % 2 == 0 {
+                amount_specified = -amount_specified;
+            }
+            let fee = fees[(next() % 4) as usize];
+
+            let pool = pool_with_positions(&positions, 60, tick);
+            let result = pool
+                .swap(
+                    zero_for_one,
+                    amount_specified,
+                    default_sqrt_price_limit(zero_for_one),
+                    fee,
+                )
+                .unwrap();
+
+            //every step trades at a price no better than the one before the swap, so the output
+            //valued at the pre-swap price plus the fee never exceeds the input. Valued at the
+            //post-swap price it does, as the last step trades at the worst price.
+            let (amount_in, amount_out) = result.io(zero_for_one);
+            let sqrt_price = pool.slot0.sqrt_price;
+            let price_x192 = sqrt_price.full_mul(sqrt_price);
+            let q192 = U512::one() << 192;
+            let amount_in_less_fee = U512::from(amount_in - result.fee_amount);
+            if zero_for_one {
+                //token1 out <= (token0 in - fee) * price
+                assert!(U512::from(amount_out) * q192 <= amount_in_less_fee * price_x192);
+            } else {
+                //token0 out * price <= token1 in - fee
+                assert!(U512::from(amount_out) * price_x192 <= amount_in_less_fee * q192);
+            }
+        }
+    }
+
+    #[test]
+    fn test_average_execution_tick() {
+        let pool = test_pool();
+        let (result, trace) = swap_traced(
+            &pool.ticks,
+            &pool.tick_bitmap,
+            pool.tick_spacing,
+            true,
+            I256::from_dec_str("30000000000000000").unwrap(),
+            default_sqrt_price_limit(true),
+            &pool.slot0,
+            3000,
+        )
+        .unwrap();
+
+        //300 ticks around -150 with 1e18, then ~193.4 ticks around ~-396.7 with 1.5e18
+        assert_eq!(average_execution_tick(&trace), Some(-271));
+        assert!(result.tick_after < -271 && -271 < pool.slot0.tick);
+
+        //a swap from tick 0 to the limit at tick 200 within one range executes around tick 100
+        let (_, trace) = swap_traced(
+            &pool.ticks,
+            &pool.tick_bitmap,
+            pool.tick_spacing,
+            false,
+            I256::from_dec_str("20000000000000000").unwrap(),
+            get_sqrt_ratio_at_tick(200).unwrap(),
+            &pool.slot0,
+            3000,
+        )
+        .unwrap();
+        assert_eq!(average_execution_tick(&trace), Some(100));
+
+        assert_eq!(average_execution_tick(&[]), None);
+    }
+
+    #[test]
+    fn test_max_amount_in_current_tick() {
+        let pool = test_pool();
+
+        //the next initialized ticks are -300 and 600
+        for (zero_for_one, tick_next, amount, liquidity_crossed) in [
+            (true, -300, "15157776661943657", 15e17 as u128),
+            (false, 600, "30544622242640680", 0),
+        ] {
+            let amount_in = max_amount_in_current_tick(&pool, zero_for_one, 3000).unwrap();
+            assert_eq!(amount_in, U256::from_dec_str(amount).unwrap());
+            let sqrt_price_next = get_sqrt_ratio_at_tick(tick_next).unwrap();
+            let swap = |amount_in| {
+                pool.swap(
+                    zero_for_one,
+                    I256::from_raw(amount_in),
+                    default_sqrt_price_limit(zero_for_one),
+                    3000,
+                )
+                .unwrap()
+            };
+
+            //stops just before the tick
+            let result = swap(amount_in);
+            assert_ne!(result.sqrt_price_after, sqrt_price_next);
+            assert_eq!(result.liquidity_after, 1e18 as u128);
+            assert_eq!(
+                result.tick_after,
+                if zero_for_one {
+                    tick_next
+                } else {
+                    tick_next - 1
+                }
+            );
+
+            //one more reaches it and crosses
+            let result = swap(amount_in + 1);
+            assert_eq!(result.sqrt_price_after, sqrt_price_next);
+            assert_eq!(result.liquidity_after, liquidity_crossed);
+        }
+
+        //sitting right on an initialized tick, selling token0 crosses it immediately
+        let pool = pool_with_positions(&[(-600, 600, 1e18 as u128)], 60, 600);
+        assert_eq!(
+            max_amount_in_current_tick(&pool, true, 3000).unwrap(),
+            U256::zero()
+        );
+    }
+
+    #[test]
+    fn test_execution_by_range() {
+        let pool = test_pool();
+        let amount_specified = I256::from_dec_str("30000000000000000").unwrap();
+        let ranges = execution_by_range(&pool, true, amount_specified, 3000).unwrap();
+
+        //[-300, 600) with 1e18 down to tick -300, then [-600, -300) with 1.5e18
+        assert_eq!(ranges.len(), 2);
+        assert_eq!((ranges[0].0, ranges[0].1), (0, -301));
+        assert_eq!((ranges[1].0, ranges[1].1), (-301, -494));
+        assert_eq!(
+            ranges[0].2,
+            U256::from_dec_str("15157776661943658").unwrap()
+        );
+
+        //the ranges add up to the whole swap
+        let result = pool
+            .swap(true, amount_specified, default_sqrt_price_limit(true), 3000)
+            .unwrap();
+        let (amount_in, amount_out) = result.io(true);
+        assert_eq!(ranges[0].2 + ranges[1].2, amount_in);
+        assert_eq!(ranges[0].3 + ranges[1].3, amount_out);
+
+        //the price worsens from one range to the next
+        let price = |range: &(i32, i32, U256, U256)| u256_to_f64(range.3) / u256_to_f64(range.2);
+        assert!(price(&ranges[0]) > price(&ranges[1]));
+    }
+
+    #[test]
+    fn test_prices() {
+        //~2000 USDC (6 decimals) per WETH (18 decimals)
+        let tick = -200311;
+        let pool = pool_with_positions(&[(-887220, 887220, 1e18 as u128)], 60, tick);
+        let (weth_per_usdc, usdc_per_weth) = prices(&pool.slot0, 18, 6);
+        assert!((usdc_per_weth - 1.0001f64.powi(tick) * 1e12).abs() / usdc_per_weth < 1e-9);
+        assert!((usdc_per_weth - 2000f64).abs() < 1f64);
+        assert!((weth_per_usdc * usdc_per_weth - 1f64).abs() <= f64::EPSILON);
+
+        for tick in [MIN_TICK, -1000, 0, 1000, MAX_TICK] {
+            let slot0 = Slot0 {
+                sqrt_price: get_sqrt_ratio_at_tick(tick).unwrap(),
+                liquidity: 0,
+                tick,
+            };
+            let (token0_per_token1, token1_per_token0) = prices(&slot0, 18, 18);
+            assert!((token0_per_token1 * token1_per_token0 - 1f64).abs() <= f64::EPSILON);
+        }
+    }
+
+    #[test]
+    fn test_swap_budgeted() {
+        let pool = test_pool();
+        //a deep swap draining the pool takes dozens of steps
+        let amount_specified = I256::from_dec_str("1000000000000000000000000000000").unwrap();
+        let expected = pool
+            .swap(true, amount_specified, default_sqrt_price_limit(true), 3000)
+            .unwrap();
+
+        let (partial, completed) = swap_budgeted(
+            &pool,
+            true,
+            amount_specified,
+            default_sqrt_price_limit(true),
+            3000,
+            3,
+        )
+        .unwrap();
+        assert!(!completed);
+        //the steps to tick 0, -300 and -600
+        assert_eq!(
+            partial.sqrt_price_after,
+            get_sqrt_ratio_at_tick(-600).unwrap()
+        );
+        assert_eq!(partial.liquidity_after, 5e17 as u128);
+        assert!(partial.amount0_delta < expected.amount0_delta);
+
+        let (result, completed) = swap_budgeted(
+            &pool,
+            true,
+            amount_specified,
+            default_sqrt_price_limit(true),
+            3000,
+            usize::MAX,
+        )
+        .unwrap();
+        assert!(completed);
+        assert_eq!(result.sqrt_price_after, expected.sqrt_price_after);
+        assert_eq!(result.amount0_delta, expected.amount0_delta);
+
+        //a swap that fits in the budget
+        let (_, completed) = swap_budgeted(
+            &pool,
+            true,
+            I256::from_dec_str("10000000000000000").unwrap(),
+            default_sqrt_price_limit(true),
+            3000,
+            2,
+        )
+        .unwrap();
+        assert!(completed);
+    }
+
+    #[test]
+    fn test_implied_fee() {
+        //within [-300, 600)
+        let pool = test_pool();
+
+        for (zero_for_one, fee) in [(true, 3000), (false, 500), (true, 10000), (false, 100)] {
+            let result = pool
+                .swap(
+                    zero_for_one,
+                    I256::from_dec_str("10000000000000000").unwrap(),
+                    default_sqrt_price_limit(zero_for_one),
+                    fee,
+                )
+                .unwrap();
+            let (amount_in, amount_out) = result.io(zero_for_one);
+
+            let implied = implied_fee(
+                amount_in,
+                amount_out,
+                pool.slot0.sqrt_price,
+                result.sqrt_price_after,
+                pool.slot0.liquidity,
+                zero_for_one,
+            )
+            .unwrap();
+            assert!((implied - fee as f64).abs() < 1e-6);
+
+            //more output than the range could have paid
+            let implied = implied_fee(
+                amount_in,
+                amount_out * 2,
+                pool.slot0.sqrt_price,
+                result.sqrt_price_after,
+                pool.slot0.liquidity,
+                zero_for_one,
+            )
+            .unwrap();
+            assert!(implied.is_nan());
+        }
+    }
+
+    #[test]
+    fn test_swap_with_tick_list() {
+        let positions = [
+            (-600, 600, 1e18 as u128),
+            (-1200, -300, 5e17 as u128),
+            (-30000, 30000, 1e17 as u128),
+        ];
+        let pool = pool_with_positions(&positions, 60, 0);
+        let mut sorted_initialized: Vec<i32> = pool.ticks.keys().copied().collect();
+        sorted_initialized.sort_unstable();
+
+        for zero_for_one in [true, false] {
+            for amount_specified in [
+                "10000000000000000",
+                "-20000000000000000",
+                "1000000000000000000",
+            ] {
+                let amount_specified = I256::from_dec_str(amount_specified).unwrap();
+                let expected = pool
+                    .swap(
+                        zero_for_one,
+                        amount_specified,
+                        default_sqrt_price_limit(zero_for_one),
+                        3000,
+                    )
+                    .unwrap();
+                let result = swap_with_tick_list(
+                    &pool.ticks,
+                    &sorted_initialized,
+                    pool.tick_spacing,
+                    zero_for_one,
+                    amount_specified,
+                    default_sqrt_price_limit(zero_for_one),
+                    &pool.slot0,
+                    3000,
+                )
+                .unwrap();
+
+                assert_eq!(result.amount0_delta, expected.amount0_delta);
+                assert_eq!(result.amount1_delta, expected.amount1_delta);
+                assert_eq!(result.sqrt_price_after, expected.sqrt_price_after);
+                assert_eq!(result.liquidity_after, expected.liquidity_after);
+                assert_eq!(result.tick_after, expected.tick_after);
+                assert_eq!(result.fee_amount, expected.fee_amount);
+            }
+        }
+    }
+
+    #[test]
+    fn test_direction_for_limit() {
+        let pool = test_pool();
+        let sqrt_price = pool.slot0.sqrt_price;
+
+        for (sqrt_price_limit, direction) in [
+            (get_sqrt_ratio_at_tick(-100).unwrap(), Some(true)),
+            (default_sqrt_price_limit(true), Some(true)),
+            (get_sqrt_ratio_at_tick(100).unwrap(), Some(false)),
+            (default_sqrt_price_limit(false), Some(false)),
+            (sqrt_price, None),
+            (get_sqrt_ratio_at_tick(MIN_TICK).unwrap(), None),
+            (get_sqrt_ratio_at_tick(MAX_TICK).unwrap(), None),
+        ] {
+            assert_eq!(
+                direction_for_limit(&pool.slot0, sqrt_price_limit),
+                direction
+            );
+            //the implied direction is accepted by swap()
+            if let Some(zero_for_one) = direction {
+                assert!(pool
+                    .swap(zero_for_one, I256::from(1000), sqrt_price_limit, 3000)
+                    .is_ok());
+                assert!(pool
+                    .swap(!zero_for_one, I256::from(1000), sqrt_price_limit, 3000)
+                    .is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn test_validate_pool() {
+        let mut pool = test_pool();
+        assert_eq!(validate_pool(&pool), Ok(()));
+
+        //exactly the max is still valid
+        let max_liquidity = crate::tick::max_liquidity_per_tick(pool.tick_spacing);
+        pool.ticks.get_mut(&600).unwrap().l_gross = max_liquidity;
+        assert_eq!(validate_pool(&pool), Ok(()));
+
+        pool.ticks.get_mut(&600).unwrap().l_gross = max_liquidity + 1;
+        pool.ticks.get_mut(&-1200).unwrap().l_gross = u128::MAX;
+        assert_eq!(validate_pool(&pool), Err(vec![-1200, 600]));
+    }
+
+    #[test]
+    fn test_price_after_swaps() {
+        let pool = test_pool();
+        let swaps = [
+            (true, I256::from_dec_str("30000000000000000").unwrap(), 3000),
+            (false, I256::from_dec_str("10000000000000000").unwrap(), 500),
+            (false, I256::from(-1e16 as i64), 3000),
+            (true, I256::from(-5e15 as i64), 10000),
+        ];
+
+        let mut sequential = MutablePoolState::new(pool.clone());
+        for &(zero_for_one, amount_specified, fee) in &swaps {
+            sequential
+                .apply_swap(
+                    zero_for_one,
+                    amount_specified,
+                    default_sqrt_price_limit(zero_for_one),
+                    fee,
+                )
+                .unwrap();
+        }
+        assert_eq!(
+            price_after_swaps(&pool, &swaps).unwrap(),
+            sequential.state.slot0.sqrt_price
+        );
+
+        //no swaps leaves the price untouched
+        assert_eq!(
+            price_after_swaps(&pool, &[]).unwrap(),
+            pool.slot0.sqrt_price
+        );
+    }
+
+    #[test]
+    fn test_output_bounds() {
+        let pool = test_pool();
+        for (zero_for_one, amount_specified) in [
+            (true, I256::from_dec_str("30000000000000000").unwrap()),
+            (false, I256::from_dec_str("10000000000000000").unwrap()),
+        ] {
+            let nominal = pool
+                .swap(
+                    zero_for_one,
+                    amount_specified,
+                    default_sqrt_price_limit(zero_for_one),
+                    3000,
+                )
+                .unwrap()
+                .io(zero_for_one)
+                .1;
+
+            let (min_out, max_out) =
+                output_bounds(&pool, zero_for_one, amount_specified, 3000, 500).unwrap();
+            assert!(min_out < nominal && nominal < max_out);
+
+            //no uncertainty collapses the bounds onto the nominal output
+            assert_eq!(
+                output_bounds(&pool, zero_for_one, amount_specified, 3000, 0).unwrap(),
+                (nominal, nominal)
+            );
+        }
+    }
+
+    #[test]
+    fn test_execution_price_tick() {
+        let pool = test_pool();
+        //(zero_for_one, amount_specified, execution price tick)
+        for (zero_for_one, amount_specified, expected) in [
+            (true, I256::from_dec_str("30000000000000000").unwrap(), -272),
+            (false, I256::from_dec_str("10000000000000000").unwrap(), 99),
+            (true, I256::from(1e14 as i64), -1),
+            (false, I256::from(-5e15 as i64), 50),
+        ] {
+            let result = pool
+                .swap(
+                    zero_for_one,
+                    amount_specified,
+                    default_sqrt_price_limit(zero_for_one),
+                    3000,
+                )
+                .unwrap();
+            let tick = execution_price_tick(&result, &pool.slot0).unwrap();
+            assert_eq!(tick, expected);
+
+            let (low, high) = if zero_for_one {
+                (result.tick_after, pool.slot0.tick)
+            } else {
+                (pool.slot0.tick, result.tick_after)
+            };
+            assert!(low <= tick && tick <= high);
+        }
+
+        //a swap that does not trade stays at the current tick
+        let result = pool
+            .swap(true, I256::zero(), default_sqrt_price_limit(true), 3000)
+            .unwrap();
+        assert_eq!(execution_price_tick(&result, &pool.slot0).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_pool_composition() {
+        let pool = test_pool();
+        //token0 of (-600, 600) above the price, token1 of (-600, 600) and (-1200, -300) below it
+        assert_eq!(
+            pool_reserves(&pool).unwrap(),
+            (
+                U256::from_dec_str("29553010879137169").unwrap(),
+                U256::from_dec_str("51225670726284381").unwrap()
+            )
+        );
+
+        let (pct_0, pct_1) = pool_composition(&pool, 18, 18, 1f64).unwrap();
+        assert!((pct_0 - 36.58516119821605).abs() < 1e-9);
+        assert!((pct_0 + pct_1 - 100f64).abs() < 1e-9);
+
+        let (pct_0, pct_1) = pool_composition(&pool, 18, 18, 2f64).unwrap();
+        assert!((pct_0 - 22.38790642665717).abs() < 1e-9);
+        assert!((pct_1 - 77.61209357334283).abs() < 1e-9);
+
+        //token1 with 6 decimals is worth 1e12 times more per raw unit
+        let (pct_0, _) = pool_composition(&pool, 18, 6, 1e-12).unwrap();
+        assert!((pct_0 - 36.58516119821605).abs() < 1e-9);
+
+        //no liquidity
+        let empty = pool_with_positions(&[], 60, 0);
+        assert_eq!(
+            pool_composition(&empty, 18, 18, 1f64).unwrap(),
+            (0f64, 0f64)
+        );
+    }
+
+    #[test]
+    fn test_swap_invalid_tick_spacing() {
+        let pool = test_pool();
+        let sorted_initialized: Vec<i32> = vec![-1200, -600, -300, 600];
+        for tick_spacing in [0, -60] {
+            let mut invalid = pool.clone();
+            invalid.tick_spacing = tick_spacing;
+            let err = invalid
+                .swap(true, I256::from(1000), default_sqrt_price_limit(true), 3000)
+                .unwrap_err();
+            assert!(matches!(err, UniswapV3MathError::InvalidTickSpacing(s) if s == tick_spacing));
+
+            let err = swap_with_tick_list(
+                &pool.ticks,
+                &sorted_initialized,
+                tick_spacing,
+                false,
+                I256::from(1000),
+                default_sqrt_price_limit(false),
+                &pool.slot0,
+                3000,
+            )
+            .unwrap_err();
+            assert!(matches!(err, UniswapV3MathError::InvalidTickSpacing(s) if s == tick_spacing));
+        }
+    }
+
+    #[test]
+    fn test_simulate_pending() {
+        let pool = test_pool();
+        let sell = (true, I256::from_dec_str("30000000000000000").unwrap(), 3000);
+        let buy = (
+            false,
+            I256::from_dec_str("20000000000000000").unwrap(),
+            3000,
+        );
+
+        let (price_sell_first, results) = simulate_pending(&pool, &[sell, buy]).unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[1].sqrt_price_after, price_sell_first);
+        assert_eq!(
+            price_after_swaps(&pool, &[sell, buy]).unwrap(),
+            price_sell_first
+        );
+
+        //the buy gets a different amount of token0 depending on whether it comes first
+        let (price_buy_first, reordered) = simulate_pending(&pool, &[buy, sell]).unwrap();
+        assert_ne!(price_sell_first, price_buy_first);
+        assert_ne!(results[1].amount0_delta, reordered[0].amount0_delta);
+
+        //nothing pending
+        let (price, results) = simulate_pending(&pool, &[]).unwrap();
+        assert_eq!(price, pool.slot0.sqrt_price);
+        assert!(results.is_empty());
+    }
+
+    #[test]
+    fn test_arb_to_external_price() {
+        let pool = test_pool();
+
+        //token0 is cheaper outside, sell it into the pool down to 0.98 / 0.997
+        let (zero_for_one, amount_in, profit) =
+            arb_to_external_price(&pool, 0.98, 18, 18, 3000).unwrap();
+        assert!(zero_for_one);
+        assert_eq!(amount_in, U256::from(8662164098229206u64));
+        assert_eq!(profit, U256::from(74807987604053u64));
+        let result = pool
+            .swap(
+                true,
+                I256::from(8662164098229206i64),
+                default_sqrt_price_limit(true),
+                3000,
+            )
+            .unwrap();
+        assert_eq!(result.tick_after, -172);
+
+        //token0 is dearer outside, buy it from the pool up to 1.02 * 0.997
+        let (zero_for_one, amount_in, profit) =
+            arb_to_external_price(&pool, 1.02, 18, 18, 3000).unwrap();
+        assert!(!zero_for_one);
+        assert_eq!(amount_in, U256::from(8459809622521651u64));
+        assert_eq!(profit, U256::from(71353673712763u64));
+
+        //the same raw price for a token0 of 6 decimals
+        assert_eq!(
+            arb_to_external_price(&pool, 1.02e-12, 6, 18, 3000).unwrap(),
+            (false, amount_in, profit)
+        );
+
+        //within the fee there is nothing to gain
+        assert_eq!(
+            arb_to_external_price(&pool, 1.001, 18, 18, 3000).unwrap(),
+            (false, U256::zero(), U256::zero())
+        );
+        assert_eq!(
+            arb_to_external_price(&pool, 0.999, 18, 18, 3000).unwrap(),
+            (true, U256::zero(), U256::zero())
+        );
+    }
+
+    #[test]
+    fn test_vwap_tick() {
+        //crosses tick -300, into 1.5 times the liquidity, and stops around tick -494
+        let pool = test_pool();
+        let amount_specified = I256::from_dec_str("30000000000000000").unwrap();
+        let vwap = vwap_tick(&pool, true, amount_specified, 3000).unwrap();
+        assert_eq!(vwap, -272);
+
+        let result = pool
+            .swap(true, amount_specified, default_sqrt_price_limit(true), 3000)
+            .unwrap();
+        assert_eq!(result.tick_after, -494);
+        //the simple average of the start and end ticks lags behind the volume
+        assert_ne!(vwap, (pool.slot0.tick + result.tick_after) / 2);
+        assert!(result.tick_after < vwap && vwap < pool.slot0.tick);
+
+        //nothing traded
+        assert_eq!(vwap_tick(&pool, false, I256::zero(), 3000).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_price_improvement_bps() {
+        let result = SwapResult {
+            amount0_delta: I256::from(1000000),
+            amount1_delta: I256::from(-990000),
+            ..Default::default()
+        };
+        assert_eq!(result.price_improvement_bps(U256::from(990000)), 0);
+        assert_eq!(result.price_improvement_bps(U256::from(980000)), 102);
+        assert_eq!(result.price_improvement_bps(U256::from(1000000)), -100);
+        //truncated towards zero on both sides
+        assert_eq!(result.price_improvement_bps(U256::from(990050)), 0);
+        assert_eq!(result.price_improvement_bps(U256::from(989950)), 0);
+
+        //output in token0
+        let result = SwapResult {
+            amount0_delta: I256::from(-500),
+            amount1_delta: I256::from(1000),
+            ..Default::default()
+        };
+        assert_eq!(result.price_improvement_bps(U256::from(250)), 10000);
+        assert_eq!(result.price_improvement_bps(U256::from(1)), 4990000);
+        assert_eq!(result.price_improvement_bps(U256::zero()), i32::MAX);
+        assert_eq!(result.price_improvement_bps(U256::MAX), -9999);
+
+        //nothing traded against nothing quoted
+        assert_eq!(SwapResult::default().price_improvement_bps(U256::zero()), 0);
+    }
+
+    #[test]
+    fn test_total_fee_growth() {
+        //crosses tick -300, so the fee is shared by two liquidities
+        let pool = test_pool();
+        let amount_specified = I256::from_dec_str("30000000000000000").unwrap();
+        let (result, fee_growth_0, fee_growth_1) =
+            total_fee_growth(&pool, true, amount_specified, 3000).unwrap();
+        assert_eq!(fee_growth_1, U256::zero());
+
+        let (expected, trace) = swap_traced(
+            &pool.ticks,
+            &pool.tick_bitmap,
+            pool.tick_spacing,
+            true,
+            amount_specified,
+            default_sqrt_price_limit(true),
+            &pool.slot0,
+            3000,
+        )
+        .unwrap();
+        assert_eq!(result.amount0_delta, expected.amount0_delta);
+        let q128 = U256::one() << 128;
+        let mut sum = U256::zero();
+        for step in trace.iter().filter(|step| step.liquidity > 0) {
+            sum += step.fee_amount * q128 / U256::from(step.liquidity);
+        }
+        assert_eq!(fee_growth_0, sum);
+        assert!(fee_growth_0 > result.fee_amount * q128 / U256::from(1500000000000000000u128));
+        assert!(fee_growth_0 < result.fee_amount * q128 / U256::from(1000000000000000000u128));
+
+        //the other direction grows the token1 global
+        let (result, fee_growth_0, fee_growth_1) =
+            total_fee_growth(&pool, false, amount_specified, 3000).unwrap();
+        assert_eq!(fee_growth_0, U256::zero());
+        assert_eq!(
+            fee_growth_1,
+            result.fee_amount * q128 / U256::from(1000000000000000000u128)
+        );
+    }
+
+    #[test]
+    fn test_tick_info_constructors() {
+        let info = TickInfo::new(-60, 300, -100);
+        assert_eq!((info.index, info.l_gross, info.l_net), (-60, 300, -100));
+
+        //lower and upper tick of a single position
+        let info = TickInfo::from_net(-60, 100);
+        assert_eq!((info.index, info.l_gross, info.l_net), (-60, 100, 100));
+        let info = TickInfo::from_net(60, -100);
+        assert_eq!((info.index, info.l_gross, info.l_net), (60, 100, -100));
+
+        let info = TickInfo::from_net(0, i128::MIN);
+        assert_eq!(info.l_gross, 1u128 << 127);
+    }
+
+    #[test]
+    fn test_amount_to_external_price() {
+        let pool = test_pool();
+
+        //0.98 is nearest to tick -202
+        let (zero_for_one, amount_in) =
+            amount_to_external_price(&pool, 0.98, 18, 18, 3000).unwrap();
+        assert!(zero_for_one);
+        assert_eq!(amount_in, U256::from(10181210691159336u64));
+        let result = pool
+            .swap(true, I256::MAX, get_sqrt_ratio_at_tick(-202).unwrap(), 3000)
+            .unwrap();
+        assert_eq!(result.amount0_delta, I256::from(10181210691159336i64));
+
+        //1.02 is nearest to tick 198
+        let (zero_for_one, amount_in) =
+            amount_to_external_price(&pool, 1.02, 18, 18, 3000).unwrap();
+        assert!(!zero_for_one);
+        assert_eq!(amount_in, U256::from(9978603035259734u64));
+        //the same raw price with a token0 of 6 decimals
+        assert_eq!(
+            amount_to_external_price(&pool, 1.02e-12, 6, 18, 3000).unwrap(),
+            (false, amount_in)
+        );
+
+        //already at the nearest tick
+        assert_eq!(
+            amount_to_external_price(&pool, 1.00001, 18, 18, 3000).unwrap(),
+            (false, U256::zero())
+        );
+    }
+
+    #[test]
+    fn test_complexity() {
+        //starts on tick 0 of word 0, then reads word -1 twice on the way to crossing -300
+        let pool = test_pool();
+        let amount_specified = I256::from_dec_str("30000000000000000").unwrap();
+        let complexity = complexity(&pool, true, amount_specified, 3000).unwrap();
+        assert_eq!(
+            complexity,
+            SwapComplexity {
+                tick_crossings: 1,
+                word_reads: 2,
+                steps: 3,
+            }
+        );
+
+        //the counters of swap_traced and swap_with_word_limit, which counts the word -1 once
+        let (_, trace) = swap_traced(
+            &pool.ticks,
+            &pool.tick_bitmap,
+            pool.tick_spacing,
+            true,
+            amount_specified,
+            default_sqrt_price_limit(true),
+            &pool.slot0,
+            3000,
+        )
+        .unwrap();
+        assert_eq!(complexity.steps, trace.len());
+        let with_word_limit = |max_word_reads| {
+            swap_with_word_limit(
+                &pool.ticks,
+                &pool.tick_bitmap,
+                pool.tick_spacing,
+                true,
+                amount_specified,
+                default_sqrt_price_limit(true),
+                &pool.slot0,
+                3000,
+                max_word_reads,
+            )
+        };
+        assert!(with_word_limit(complexity.word_reads).is_ok());
+        assert!(with_word_limit(complexity.word_reads - 1).is_err());
+        assert_eq!(
+            execution_by_range(&pool, true, amount_specified, 3000)
+                .unwrap()
+                .len(),
+            complexity.tick_crossings + 1
+        );
+    }
+
+    #[test]
+    fn test_bid_ask_spread_bps() {
+        let pool = test_pool();
+        let spread = |size: u64| bid_ask_spread_bps(&pool, U256::from(size), 3000).unwrap();
+
+        //twice the fee for a size too small to move the price
+        assert!((spread(1000000000000) - 60.11).abs() < 0.01);
+        assert!((spread(1000000000000000) - 80.06).abs() < 0.01);
+        assert!((spread(10000000000000000) - 259.79).abs() < 0.01);
+        assert!(spread(1000000000000) < spread(1000000000000000));
+        assert!(spread(1000000000000000) < spread(10000000000000000));
+
+        //more token0 than the pool holds
+        assert_eq!(spread(100000000000000000), f64::INFINITY);
+        assert_eq!(spread(0), f64::INFINITY);
+    }
+
+    #[test]
+    fn test_price_from_reserves() {
+        assert_eq!(
+            price_from_reserves(U256::from(100), U256::from(250), 18, 18),
+            2.5
+        );
+        //1000 token0 of 6 decimals for 2500 token1 of 18 decimals
+        let price = price_from_reserves(
+            U256::from(1000) * U256::exp10(6),
+            U256::from(2500) * U256::exp10(18),
+            6,
+            18,
+        );
+        assert!((price - 2.5).abs() < 1e-12);
+        assert_eq!(
+            price_from_reserves(U256::zero(), U256::from(1), 18, 18),
+            f64::INFINITY
+        );
+
+        //reserves of a full range pool match its spot price
+        let (min_tick, max_tick) = ((MIN_TICK / 60) * 60, (MAX_TICK / 60) * 60);
+        for tick in [-50000, 0, 1234, 80000] {
+            let pool = pool_with_positions(&[(min_tick, max_tick, 1e21 as u128)], 60, tick);
+            let (reserve0, reserve1) = pool_reserves(&pool).unwrap();
+            let expected = tick_to_price(tick, 18, 6);
+            let price = price_from_reserves(reserve0, reserve1, 18, 6);
+            assert!((price - expected).abs() / expected < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_divergence_sweep() {
+        let pool = test_pool();
+        let sizes: Vec<U256> = [1e12, 1e14, 1e15, 1e16, 3e16]
+            .iter()
+            .map(|&size| U256::from(size as u128))
+            .collect();
+        for zero_for_one in [true, false] {
+            for (decimals_0, decimals_1) in [(18, 18), (6, 18)] {
+                let errors =
+                    divergence_sweep(&pool, zero_for_one, &sizes, 3000, decimals_0, decimals_1)
+                        .unwrap();
+                assert_eq!(errors.len(), sizes.len());
+                for error in errors {
+                    assert!(error < 1e-9, "{}", error);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_best_pool() {
+        let shallow = test_pool();
+        let deep = pool_with_positions(&[(-600, 600, 5e18 as u128)], 60, 0);
+        let pools = [shallow.clone(), deep.clone()];
+
+        //exact input, the deeper pool pays out more
+        let amount_in = I256::from_dec_str("10000000000000000").unwrap();
+        let (index, result) = best_pool(&pools, true, amount_in, 3000).unwrap().unwrap();
+        assert_eq!(index, 1);
+        let shallow_result = shallow
+            .swap(true, amount_in, default_sqrt_price_limit(true), 3000)
+            .unwrap();
+        assert!(result.io(true).1 > shallow_result.io(true).1);
+
+        //exact output, the deeper pool asks for less
+        let (index, result) = best_pool(&pools, false, -amount_in, 3000).unwrap().unwrap();
+        assert_eq!(index, 1);
+        assert_eq!(result.amount0_delta, -amount_in);
+
+        //ties go to the first pool
+        let (index, _) = best_pool(&[deep.clone(), deep], true, amount_in, 3000)
+            .unwrap()
+            .unwrap();
+        assert_eq!(index, 0);
+
+        assert!(best_pool(&[], true, amount_in, 3000).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_pool_balance_change() {
+        let pool = test_pool();
+        let amount = I256::from(1000000000000000i64);
+
+        //selling token0, the pool takes in token0 and pays out token1
+        let result = pool
+            .swap(true, amount, default_sqrt_price_limit(true), 3000)
+            .unwrap();
+        let (token0, token1) = result.pool_balance_change();
+        assert_eq!(token0, amount);
+        assert!(token1.is_negative());
+        assert_eq!(
+            (token0.unsigned_abs(), token1.unsigned_abs()),
+            result.io(true)
+        );
+
+        //exact output of token0, the pool pays out exactly the amount
+        let result = pool
+            .swap(false, -amount, default_sqrt_price_limit(false), 3000)
+            .unwrap();
+        let (token0, token1) = result.pool_balance_change();
+        assert_eq!(token0, -amount);
+        assert!(token1.is_positive());
+    }
+
+    #[test]
+    fn test_swap_one_wei() {
+        let pool = pool_with_positions(&[(-600, 600, 1e24 as u128)], 60, 0);
+
+        //the wei is taken as fee and nothing comes out, the price does not move
+        for zero_for_one in [true, false] {
+            let result = pool
+                .swap(
+                    zero_for_one,
+                    I256::one(),
+                    default_sqrt_price_limit(zero_for_one),
+                    3000,
+                )
+                .unwrap();
+            assert_eq!(result.io(zero_for_one), (U256::one(), U256::zero()));
+            assert_eq!(result.fee_amount, U256::one());
+            assert_eq!(result.sqrt_price_after, pool.slot0.sqrt_price);
+        }
+
+        //one wei out still costs a rounded up input
+        let result = pool
+            .swap(true, -I256::one(), default_sqrt_price_limit(true), 3000)
+            .unwrap();
+        assert_eq!(result.io(true), (U256::from(3), U256::one()));
+    }
+
+    #[test]
+    fn test_fee_growth_over_swaps() {
+        let pool = test_pool();
+        let swaps = [
+            (true, I256::from(10000000000000000i64), 3000),
+            (false, I256::from(30000000000000000i64), 3000),
+            (true, I256::from(5000000000000000i64), 3000),
+        ];
+        let (fee_growth_0, fee_growth_1) = fee_growth_over_swaps(&pool, &swaps).unwrap();
+        assert_eq!(
+            fee_growth_0,
+            U256::from_dec_str("15312706511442230855851857334429569").unwrap()
+        );
+        //the second swap starts from where the first one left the price
+        assert_eq!(
+            fee_growth_1,
+            U256::from_dec_str("30625413022884801994070635607322601").unwrap()
+        );
+
+        //a single swap is total_fee_growth()
+        let (_, expected_0, expected_1) = total_fee_growth(&pool, true, swaps[0].1, 3000).unwrap();
+        assert_eq!(
+            fee_growth_over_swaps(&pool, &swaps[..1]).unwrap(),
+            (expected_0, expected_1)
+        );
+
+        assert_eq!(
+            fee_growth_over_swaps(&pool, &[]).unwrap(),
+            (U256::zero(), U256::zero())
+        );
+    }
+
+    #[test]
+    fn test_fee_equals_impact_size() {
+        //within the range around tick 0, selling x net of the fee into L at price 1 costs x^2 / (L + x)
+        //of impact, which equals the fee x * fee / (1 - fee) at x = L * fee / (1 - 2 * fee)
+        let pool = test_pool();
+        let expected = 1e18 * 0.003 / 0.994 / 0.997;
+        for zero_for_one in [true, false] {
+            let size = fee_equals_impact_size(&pool, zero_for_one, 3000).unwrap();
+            assert!((u256_to_f64(size) / expected - 1f64).abs() < 1e-6);
+        }
+
+        //a lower fee is caught up with sooner
+        let size_500 = fee_equals_impact_size(&pool, true, 500).unwrap();
+        assert!(size_500 < fee_equals_impact_size(&pool, true, 3000).unwrap());
+
+        //no fee, any impact is more
+        assert!(fee_equals_impact_size(&pool, true, 0).unwrap() <= U256::from(2));
+    }
+
+    #[test]
+    fn test_swap_with_protocol_fee() {
+        let pool = test_pool();
+        let amount_specified = I256::from(30000000000000000i64);
+        let swap_pool = |zero_for_one: bool| {
+            swap_with_protocol_fee(
+                &pool.ticks,
+                &pool.tick_bitmap,
+                pool.tick_spacing,
+                zero_for_one,
+                amount_specified,
+                default_sqrt_price_limit(zero_for_one),
+                &pool.slot0,
+                3000,
+                4,
+                6,
+            )
+            .unwrap()
+        };
+
+        //selling token0 takes a quarter of the fee, rounded down in each of the two steps
+        let result = swap_pool(true);
+        assert_eq!(result.fee_amount, U256::from(90000000000001u64));
+        assert_eq!(result.protocol_fee, U256::from(22499999999999u64));
+        //the protocol fee does not change what the trader pays or gets
+        let expected = pool
+            .swap(true, amount_specified, default_sqrt_price_limit(true), 3000)
+            .unwrap();
+        assert_eq!(result.amount1_delta, expected.amount1_delta);
+        assert_eq!(result.sqrt_price_after, expected.sqrt_price_after);
+        assert_eq!(expected.protocol_fee, U256::zero());
+
+        //selling token1 takes a sixth
+        let result = swap_pool(false);
+        assert_eq!(result.fee_amount, U256::from(90000000000000u64));
+        assert_eq!(result.protocol_fee, U256::from(15000000000000u64));
+
+        //no protocol fee for a zero denominator
+        let result = swap_with_protocol_fee(
+            &pool.ticks,
+            &pool.tick_bitmap,
+            pool.tick_spacing,
+            false,
+            amount_specified,
+            default_sqrt_price_limit(false),
+            &pool.slot0,
+            3000,
+            4,
+            0,
+        )
+        .unwrap();
+        assert_eq!(result.protocol_fee, U256::zero());
+    }
+
+    // A single range pool computing its state on demand and recording what the swap reads
+    struct MockSnapshot {
+        tick_reads: RefCell<Vec<i32>>,
+        word_reads: RefCell<Vec<i16>>,
+    }
+
+    impl PoolSnapshot for MockSnapshot {
+        fn slot0(&self) -> Slot0 {
+            Slot0 {
+                sqrt_price: get_sqrt_ratio_at_tick(0).unwrap(),
+                liquidity: 1e18 as u128,
+                tick: 0,
+            }
+        }
+
+        fn tick(&self, tick: i32) -> Option<TickInfo> {
+            self.tick_reads.borrow_mut().push(tick);
+            match tick {
+                -600 => Some(TickInfo::from_net(tick, 1e18 as i128)),
+                600 => Some(TickInfo::from_net(tick, -1e18 as i128)),
+                _ => None,
+            }
+        }
+
+        fn bitmap_word(&self, word_pos: i16) -> U256 {
+            self.word_reads.borrow_mut().push(word_pos);
+            // ticks -600 and 600 at spacing 60, compressed to -10 and 10
+            match word_pos {
+                -1 => U256::one() << 246,
+                0 => U256::one() << 10,
+                _ => U256::zero(),
+            }
+        }
+
+        fn tick_spacing(&self) -> i32 {
+            60
+        }
+
+        fn fee(&self) -> u32 {
+            500
+        }
+    }
+
+    #[test]
+    fn test_swap_pool_snapshot() {
+        let mock = MockSnapshot {
+            tick_reads: RefCell::new(vec![]),
+            word_reads: RefCell::new(vec![]),
+        };
+        let pool = pool_with_positions(&[(-600, 600, 1e18 as u128)], 60, 0);
+
+        //through the whole range, crossing tick -600 out of all liquidity
+        let amount_specified = I256::from(100000000000000000i64);
+        let result = swap(
+            &mock,
+            true,
+            amount_specified,
+            default_sqrt_price_limit(true),
+        )
+        .unwrap();
+        //swapped at the fee of the snapshot
+        assert_eq!(mock.fee(), 500);
+        let expected = pool
+            .swap(true, amount_specified, default_sqrt_price_limit(true), 500)
+            .unwrap();
+        assert_eq!(result.fee_amount, expected.fee_amount);
+        assert_ne!(
+            result.fee_amount,
+            pool.swap(true, amount_specified, default_sqrt_price_limit(true), 3000)
+                .unwrap()
+                .fee_amount
+        );
+        assert_eq!(result.amount0_delta, expected.amount0_delta);
+        assert_eq!(result.amount1_delta, expected.amount1_delta);
+        assert_eq!(result.sqrt_price_after, expected.sqrt_price_after);
+        assert_eq!(mock.tick_reads.borrow().as_slice(), &[-600]);
+        assert_eq!(mock.word_reads.borrow()[..2], [0, -1]);
+
+        //a PoolState is a snapshot together with a fee tier
+        let result = swap(
+            &(&pool, 500),
+            true,
+            amount_specified,
+            default_sqrt_price_limit(true),
+        )
+        .unwrap();
+        assert_eq!(result.amount1_delta, expected.amount1_delta);
+    }
+
+    #[test]
+    fn test_swap_full_trace() {
+        let pool = test_pool();
+        for (zero_for_one, amount_specified) in [
+            (true, I256::from(30000000000000000i64)),
+            (false, I256::from(-30000000000000000i64)),
+        ] {
+            let (result, states) = swap_full_trace(
+                &pool.ticks,
+                &pool.tick_bitmap,
+                pool.tick_spacing,
+                zero_for_one,
+                amount_specified,
+                default_sqrt_price_limit(zero_for_one),
+                &pool.slot0,
+                3000,
+            )
+            .unwrap();
+            let (_, trace) = swap_traced(
+                &pool.ticks,
+                &pool.tick_bitmap,
+                pool.tick_spacing,
+                zero_for_one,
+                amount_specified,
+                default_sqrt_price_limit(zero_for_one),
+                &pool.slot0,
+                3000,
+            )
+            .unwrap();
+            assert_eq!(states.len(), trace.len());
+            for (state, step) in states.iter().zip(&trace) {
+                assert_eq!(state.sqrt_price_x96, step.sqrt_price_end_x96);
+                assert_eq!(state.tick, step.tick_end);
+            }
+
+            //the specified token is token0 when selling it for an exact input and when buying it for
+            //an exact output
+            let (amount_specified_delta, amount_calculated_delta) =
+                if zero_for_one == amount_specified.is_positive() {
+                    (result.amount0_delta, result.amount1_delta)
+                } else {
+                    (result.amount1_delta, result.amount0_delta)
+                };
+            let last = states.last().unwrap();
+            assert!(last.amount_specified_remaining.is_zero());
+            assert_eq!(amount_specified_delta, amount_specified);
+            assert_eq!(last.amount_calculated, amount_calculated_delta);
+            assert_eq!(last.sqrt_price_x96, result.sqrt_price_after);
+            assert_eq!(last.tick, result.tick_after);
+            assert_eq!(last.liquidity, result.liquidity_after);
+            assert_eq!(last.fee_amount, result.fee_amount);
+            assert_eq!(
+                (last.sqrt_price_min, last.sqrt_price_max),
+                result.price_range()
+            );
+        }
+    }
+
+    #[test]
+    fn test_limit_for_avg_price() {
+        let pool = test_pool();
+        let amount_specified = I256::from(30000000000000000i64);
+        let avg_price = |zero_for_one: bool, sqrt_price_limit: U256| {
+            let result = pool
+                .swap(zero_for_one, amount_specified, sqrt_price_limit, 3000)
+                .unwrap();
+            let (amount_in, amount_out) = result.io(zero_for_one);
+            u256_to_f64(amount_in) / u256_to_f64(amount_out)
+        };
+
+        for zero_for_one in [true, false] {
+            //the whole swap averages around 1.03 input per output, the fee alone costs 1.003
+            let full = avg_price(zero_for_one, default_sqrt_price_limit(zero_for_one));
+            assert!(full > 1.02 && full < 1.04);
+
+            let limit =
+                limit_for_avg_price(&pool, zero_for_one, amount_specified, 1.008, 18, 18, 3000)
+                    .unwrap()
+                    .unwrap();
+            assert!(avg_price(zero_for_one, limit) <= 1.008);
+            let next_limit = if zero_for_one { limit - 1 } else { limit + 1 };
+            assert!(avg_price(zero_for_one, next_limit) > 1.008);
+
+            assert_eq!(
+                limit_for_avg_price(&pool, zero_for_one, amount_specified, 2f64, 18, 18, 3000)
+                    .unwrap(),
+                Some(default_sqrt_price_limit(zero_for_one))
+            );
+            //below the fee, not even the first wei past the current price is within the max
+            assert_eq!(
+                limit_for_avg_price(&pool, zero_for_one, amount_specified, 1.002, 18, 18, 3000)
+                    .unwrap(),
+                None
+            );
+        }
+
+        //the price is adjusted for the decimals, a raw token0 of 6 decimals is worth 1e12 times more
+        let limit = limit_for_avg_price(&pool, true, amount_specified, 1.008e12, 6, 18, 3000)
+            .unwrap()
+            .unwrap();
+        let limit_18 = limit_for_avg_price(&pool, true, amount_specified, 1.008, 18, 18, 3000)
+            .unwrap()
+            .unwrap();
+        assert!((u256_to_f64(limit) / u256_to_f64(limit_18) - 1f64).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_normalize_direction() {
+        let weth = Address::from_low_u64_be(1);
+        let usdc = Address::from_low_u64_be(2);
+        let dai = Address::from_low_u64_be(3);
+
+        assert_eq!(normalize_direction(weth, usdc, weth, usdc), Some(true));
+        assert_eq!(normalize_direction(weth, usdc, usdc, weth), Some(false));
+        //the same pair with the pool's tokens the other way round
+        assert_eq!(normalize_direction(usdc, weth, weth, usdc), Some(false));
+        assert_eq!(normalize_direction(usdc, weth, usdc, weth), Some(true));
+
+        assert_eq!(normalize_direction(weth, usdc, weth, dai), None);
+        assert_eq!(normalize_direction(weth, usdc, dai, usdc), None);
+        assert_eq!(normalize_direction(weth, usdc, weth, weth), None);
+    }
+
+    #[test]
+    fn test_verify_quote_opposite_sign() {
+        //a quote with the direction of token1 flipped is off by twice the amount
+        let actual = SwapResult {
+            amount0_delta: I256::from(1000000),
+            amount1_delta: I256::from(-2000000),
+            ..Default::default()
+        };
+        let quoted = SwapResult {
+            amount1_delta: I256::from(2000000),
+            ..actual.clone()
+        };
+        assert_eq!(
+            verify_quote(&quoted, &actual, 100).unwrap_err(),
+            QuoteMismatch {
+                token: 1,
+                quoted: I256::from(2000000),
+                actual: I256::from(-2000000),
+                deviation_bps: U256::from(20000),
+            }
+        );
+
+        //at the bounds of I256 the difference does not fit into it, and is still reported
+        let actual = SwapResult {
+            amount0_delta: I256::MIN,
+            ..Default::default()
+        };
+        let quoted = SwapResult {
+            amount0_delta: I256::MAX,
+            ..Default::default()
+        };
+        let mismatch = verify_quote(&quoted, &actual, 10000).unwrap_err();
+        assert_eq!(mismatch.token, 0);
+        assert_eq!(mismatch.quoted, I256::MAX);
+        assert_eq!(mismatch.actual, I256::MIN);
+    }
 }
@@ -30,11 +30,7 @@ pub fn compute_swap_step(
     let mut amount_out = U256::zero();
 
     if exact_in {
-        let amount_remaining_less_fee = mul_div(
-            amount_remaining.into_raw(),
-            U256::from(1e6 as u32 - fee_pips), //1e6 - fee_pips
-            U256::from(1e6 as u32),            //1e6
-        )?;
+        let amount_remaining_less_fee = amount_in_less_fee(amount_remaining.into_raw(), fee_pips)?;
 
         amount_in = if zero_for_one {
             _get_amount_0_delta(
@@ -149,11 +145,31 @@ pub fn compute_swap_step(
     }
 }
 
+// The part of an exact input amount that is swapped once the fee is taken, rounded down as in
+// compute_swap_step
+pub fn amount_in_less_fee(amount_in: U256, fee_pips: u32) -> Result<U256, UniswapV3MathError> {
+    mul_div(
+        amount_in,
+        U256::from(1e6 as u32 - fee_pips), //1e6 - fee_pips
+        U256::from(1e6 as u32),            //1e6
+    )
+}
+
+// The smallest exact input amount whose amount_in_less_fee() is net_input, i.e. net_input with the fee
+// on top: net_input * 1e6 / (1e6 - fee_pips), rounded up
+pub fn gross_up_for_fee(net_input: U256, fee_pips: u32) -> Result<U256, UniswapV3MathError> {
+    mul_div_rounding_up(
+        net_input,
+        U256::from(1e6 as u32),
+        U256::from((1e6 as u32).saturating_sub(fee_pips)),
+    )
+}
+
 mod test {
     #[allow(unused)]
     use crate::sqrt_price_math::{get_next_sqrt_price_from_input, get_next_sqrt_price_from_output};
     #[allow(unused)]
-    use crate::swap_math::compute_swap_step;
+    use crate::swap_math::{amount_in_less_fee, compute_swap_step, gross_up_for_fee};
     #[allow(unused)]
     use ethers::types::{I256, U256};
 
@@ -400,4 +416,77 @@ mod test {
         assert_eq!(amount_in, U256::one());
         assert_eq!(fee_amount, U256::one());
     }
+
+    #[test]
+    fn test_compute_swap_step_fee_at_target() {
+        //when the step reaches its target the fee is not the rest of the input but
+        //mulDivRoundingUp(amountIn, feePips, 1e6 - feePips), as in SwapMath.computeSwapStep
+
+        //------------------------------------------------------------
+
+        //exact amount in that reaches tick -60 from tick 0 in zero for one
+        let price = U256::from_dec_str("79228162514264337593543950336").unwrap();
+        let price_target = U256::from_dec_str("78990846045029531151608375686").unwrap();
+        let liquidity = 1e18 as u128;
+        let amount = I256::from_dec_str("1000000000000000000").unwrap();
+
+        let (sqrt_p, amount_in, amount_out, fee_amount) =
+            compute_swap_step(price, price_target, liquidity, amount, 3000).unwrap();
+
+        assert_eq!(sqrt_p, price_target);
+        assert_eq!(amount_in, U256::from_dec_str("3004354062741926").unwrap());
+        assert_eq!(amount_out, U256::from_dec_str("2995354955910780").unwrap());
+        //3004354062741926 * 3000 / 997000 = 9040182736435.08..., rounded up
+        assert_eq!(fee_amount, U256::from_dec_str("9040182736436").unwrap());
+        assert!(amount_in + fee_amount < amount.into_raw());
+
+        //------------------------------------------------------------
+
+        //exact amount out that reaches the same tick pays the same fee
+        let amount = I256::from_dec_str("-1000000000000000000").unwrap();
+
+        let (sqrt_p, amount_in, amount_out, fee_amount) =
+            compute_swap_step(price, price_target, liquidity, amount, 3000).unwrap();
+
+        assert_eq!(sqrt_p, price_target);
+        assert_eq!(amount_in, U256::from_dec_str("3004354062741926").unwrap());
+        assert_eq!(amount_out, U256::from_dec_str("2995354955910780").unwrap());
+        assert_eq!(fee_amount, U256::from_dec_str("9040182736436").unwrap());
+
+        //------------------------------------------------------------
+
+        //exact amount in that reaches tick 60 from tick 0 in one for zero
+        let price_target = U256::from_dec_str("79466191966197645195421774833").unwrap();
+        let amount = I256::from_dec_str("1000000000000000000").unwrap();
+
+        let (sqrt_p, amount_in, amount_out, fee_amount) =
+            compute_swap_step(price, price_target, liquidity, amount, 500).unwrap();
+
+        assert_eq!(sqrt_p, price_target);
+        assert_eq!(amount_in, U256::from_dec_str("3004354062741926").unwrap());
+        assert_eq!(amount_out, U256::from_dec_str("2995354955910780").unwrap());
+        //3004354062741926 * 500 / 999500 = 1502928495618.77..., rounded up
+        assert_eq!(fee_amount, U256::from_dec_str("1502928495619").unwrap());
+    }
+
+    #[test]
+    fn test_gross_up_for_fee() {
+        for fee_pips in [0, 100, 500, 3000, 10000] {
+            for net_input in [1u64, 997, 1000, 123456789, 1000000000000000000] {
+                let net_input = U256::from(net_input);
+                let gross = gross_up_for_fee(net_input, fee_pips).unwrap();
+                assert_eq!(amount_in_less_fee(gross, fee_pips).unwrap(), net_input);
+                //and no less would do
+                assert!(amount_in_less_fee(gross - 1, fee_pips).unwrap() < net_input);
+            }
+        }
+
+        assert_eq!(
+            gross_up_for_fee(U256::from(997), 3000).unwrap(),
+            U256::from(1000)
+        );
+        assert_eq!(gross_up_for_fee(U256::zero(), 3000).unwrap(), U256::zero());
+        //nothing is left of any input at a 100% fee
+        assert!(gross_up_for_fee(U256::one(), 1000000).is_err());
+    }
 }
@@ -1,4 +1,12 @@
 use ethers::types::U256;
+use hashbrown::HashMap;
+
+use crate::error::UniswapV3MathError;
+use crate::liquidity_amounts;
+use crate::liquidity_math;
+use crate::swap::TickInfo;
+use crate::tick_bitmap;
+use crate::tick_math::{MAX_TICK, MIN_TICK};
 
 pub struct Tick {
     pub liquidity_gross: u128,
@@ -10,3 +18,275 @@ pub struct Tick {
     pub seconds_outside: u32,
     pub initialized: bool,
 }
+
+// Mint(address sender, address indexed owner, int24 indexed tickLower, int24 indexed tickUpper, uint128 amount, uint256 amount0, uint256 amount1)
+// reduced to what the tick map depends on
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MintEvent {
+    pub tick_lower: i32,
+    pub tick_upper: i32,
+    pub amount: u128,
+}
+
+// Burn(address indexed owner, int24 indexed tickLower, int24 indexed tickUpper, uint128 amount, uint256 amount0, uint256 amount1)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BurnEvent {
+    pub tick_lower: i32,
+    pub tick_upper: i32,
+    pub amount: u128,
+}
+
+// Adds liquidity_delta to a position's bounds like Tick.update, flipping a bound in the bitmap when its
+// gross liquidity goes from or to zero. Bounds left without liquidity are removed, as Tick.clear does.
+// The bounds are checked first like Position.checkTicks, leaving the maps untouched if they fail.
+pub fn apply_liquidity_delta(
+    ticks: &mut HashMap<i32, TickInfo>,
+    tick_bitmap: &mut HashMap<i16, U256>,
+    tick_lower: i32,
+    tick_upper: i32,
+    liquidity_delta: i128,
+    tick_spacing: i32,
+) -> Result<(), UniswapV3MathError> {
+    liquidity_amounts::exit_ticks(tick_lower, tick_upper)?;
+    for (tick, upper) in [(tick_lower, false), (tick_upper, true)] {
+        let info = get_or_default(ticks, tick);
+        let l_gross = liquidity_math::add_delta(info.l_gross, liquidity_delta)?;
+        // the upper bound drops the liquidity again when crossed from left to right
+        let l_net = if upper {
+            info.l_net - liquidity_delta
+        } else {
+            info.l_net + liquidity_delta
+        };
+        if (l_gross == 0) != (info.l_gross == 0) {
+            tick_bitmap::flip_tick(tick_bitmap, tick, tick_spacing)?;
+        }
+        if l_gross == 0 {
+            ticks.remove(&tick);
+        } else {
+            ticks.insert(tick, TickInfo::new(tick, l_gross, l_net));
+        }
+    }
+    Ok(())
+}
+
+// Tick map and bitmap of a pool rebuilt from its Mint and Burn events. All mints are applied before the
+// burns, so a burn never runs ahead of the liquidity it removes.
+pub fn build_from_events(
+    mints: &[MintEvent],
+    burns: &[BurnEvent],
+    tick_spacing: i32,
+) -> Result<(HashMap<i32, TickInfo>, HashMap<i16, U256>), UniswapV3MathError> {
+    let mut ticks = HashMap::new();
+    let mut tick_bitmap = HashMap::new();
+    let deltas = mints
+        .iter()
+        .map(|mint| (mint.tick_lower, mint.tick_upper, mint.amount as i128))
+        .chain(
+            burns
+                .iter()
+                .map(|burn| (burn.tick_lower, burn.tick_upper, -(burn.amount as i128))),
+        );
+    for (tick_lower, tick_upper, liquidity_delta) in deltas {
+        apply_liquidity_delta(
+            &mut ticks,
+            &mut tick_bitmap,
+            tick_lower,
+            tick_upper,
+            liquidity_delta,
+            tick_spacing,
+        )?;
+    }
+    Ok((ticks, tick_bitmap))
+}
+
+// Returns the info of the given tick, or an empty one if the tick is not initialized
+pub fn get_or_default(ticks: &HashMap<i32, TickInfo>, tick: i32) -> TickInfo {
+    ticks
+        .get(&tick)
+        .cloned()
+        .unwrap_or_else(|| TickInfo::new(tick, 0, 0))
+}
+
+// The most liquidity a single tick can reference, spread evenly so that all usable ticks together
+// cannot overflow the pool's u128 liquidity
+pub fn max_liquidity_per_tick(tick_spacing: i32) -> u128 {
+    u128::MAX / usable_tick_count(tick_spacing)
+}
+
+// The number of ticks usable at tick_spacing, from the lowest to the highest multiple of it
+fn usable_tick_count(tick_spacing: i32) -> u128 {
+    let min_tick = (MIN_TICK / tick_spacing) * tick_spacing;
+    let max_tick = (MAX_TICK / tick_spacing) * tick_spacing;
+    ((max_tick - min_tick) / tick_spacing) as u128 + 1
+}
+
+// The most liquidity that can ever be active at once at tick_spacing. Every position active at the
+// current tick has its lower tick at or below it and its upper tick above it, and each of those ticks
+// references at most max_liquidity_per_tick(). So the active liquidity is bounded by
+// max_liquidity_per_tick() times the number of usable ticks on the smaller side of the current tick,
+// which is largest with the current tick in the middle: half of the usable ticks, rounded down. That
+// is just under half of u128::MAX, reached by nested positions pairing each tick below the middle with
+// one above it.
+pub fn max_active_liquidity(tick_spacing: i32) -> u128 {
+    max_liquidity_per_tick(tick_spacing) * (usable_tick_count(tick_spacing) / 2)
+}
+
+#[cfg(test)]
+mod test {
+    use hashbrown::HashMap;
+
+    use crate::{
+        error::UniswapV3MathError,
+        swap::{test::pool_with_positions, TickInfo},
+        tick_bitmap::next_initialized_tick_within_one_word,
+        tick_math::MAX_TICK,
+    };
+
+    use super::{
+        apply_liquidity_delta, build_from_events, get_or_default, max_active_liquidity,
+        max_liquidity_per_tick, BurnEvent, MintEvent,
+    };
+
+    #[test]
+    fn test_get_or_default() {
+        let mut ticks = HashMap::new();
+        ticks.insert(
+            60,
+            TickInfo {
+                index: 60,
+                l_gross: 100,
+                l_net: -100,
+            },
+        );
+
+        //initialized tick
+        let info = get_or_default(&ticks, 60);
+        assert_eq!((info.index, info.l_gross, info.l_net), (60, 100, -100));
+
+        //uninitialized tick
+        let info = get_or_default(&ticks, 120);
+        assert_eq!((info.index, info.l_gross, info.l_net), (120, 0, 0));
+    }
+
+    #[test]
+    fn test_max_liquidity_per_tick() {
+        assert_eq!(max_liquidity_per_tick(1), 191757530477355301479181766273477);
+        assert_eq!(
+            max_liquidity_per_tick(10),
+            1917569901783203986719870431555990
+        );
+        assert_eq!(
+            max_liquidity_per_tick(60),
+            11505743598341114571880798222544994
+        );
+        assert_eq!(
+            max_liquidity_per_tick(200),
+            38350317471085141830651933667504588
+        );
+    }
+
+    #[test]
+    fn test_build_from_events() {
+        let mints = [
+            MintEvent {
+                tick_lower: -600,
+                tick_upper: 600,
+                amount: 1e18 as u128,
+            },
+            MintEvent {
+                tick_lower: -1200,
+                tick_upper: -600,
+                amount: 5e17 as u128,
+            },
+        ];
+        //partial burn of the first position, full burn of the second
+        let burns = [
+            BurnEvent {
+                tick_lower: -600,
+                tick_upper: 600,
+                amount: 4e17 as u128,
+            },
+            BurnEvent {
+                tick_lower: -1200,
+                tick_upper: -600,
+                amount: 5e17 as u128,
+            },
+        ];
+        let (ticks, tick_bitmap) = build_from_events(&mints, &burns, 60).unwrap();
+
+        let expected = pool_with_positions(&[(-600, 600, 6e17 as u128)], 60, 0);
+        assert_eq!(ticks.len(), 2);
+        for (index, info) in &expected.ticks {
+            let built = &ticks[index];
+            assert_eq!(
+                (built.index, built.l_gross, built.l_net),
+                (info.index, info.l_gross, info.l_net)
+            );
+        }
+        //-1200 is no longer initialized, -600 still is
+        for tick in [-1200, -600, 0, 600] {
+            assert_eq!(
+                next_initialized_tick_within_one_word(&tick_bitmap, tick, 60, true).unwrap(),
+                next_initialized_tick_within_one_word(&expected.tick_bitmap, tick, 60, true)
+                    .unwrap()
+            );
+        }
+        assert!(
+            !next_initialized_tick_within_one_word(&tick_bitmap, -1200, 60, true)
+                .unwrap()
+                .1
+        );
+
+        //burning more than was minted fails
+        let burns = [BurnEvent {
+            tick_lower: -600,
+            tick_upper: 600,
+            amount: 2e18 as u128,
+        }];
+        assert!(build_from_events(&mints, &burns, 60).is_err());
+
+        //a range with its bounds swapped, or past the usable ticks, fails instead of corrupting l_net
+        let swapped = [MintEvent {
+            tick_lower: 600,
+            tick_upper: -600,
+            amount: 1e18 as u128,
+        }];
+        assert!(matches!(
+            build_from_events(&swapped, &[], 60),
+            Err(UniswapV3MathError::Tlu)
+        ));
+        let mut ticks = expected.ticks.clone();
+        let mut tick_bitmap = expected.tick_bitmap.clone();
+        assert!(matches!(
+            apply_liquidity_delta(
+                &mut ticks,
+                &mut tick_bitmap,
+                -600,
+                MAX_TICK + 1,
+                1e18 as i128,
+                1
+            ),
+            Err(UniswapV3MathError::Tum)
+        ));
+        assert_eq!(ticks[&-600].l_net, expected.ticks[&-600].l_net);
+    }
+
+    #[test]
+    fn test_max_active_liquidity() {
+        //29575 usable ticks at spacing 60, 14787 on each side of the middle one
+        assert_eq!(max_active_liquidity(60), max_liquidity_per_tick(60) * 14787);
+        assert_eq!(
+            max_active_liquidity(60),
+            170135430588670061174401363316772826278
+        );
+        assert_eq!(
+            max_active_liquidity(1),
+            170141087581703993054036564125000484744
+        );
+        //fewer, larger ticks leave more of u128::MAX unused
+        for tick_spacing in [1, 10, 60, 200] {
+            assert!(max_active_liquidity(tick_spacing) <= u128::MAX / 2);
+        }
+        assert!(max_active_liquidity(200) < max_active_liquidity(1));
+    }
+}
@@ -1,10 +1,10 @@
-use crate::{abi, bit_math, error::UniswapV3MathError};
+use crate::{abi, bit_math, error::UniswapV3MathError, tick_math};
 use ethers::{
     providers::Middleware,
     types::{BlockNumber, H160, U256},
 };
 use hashbrown::HashMap;
-use std::sync::Arc;
+use std::{collections::BTreeMap, sync::Arc};
 
 //Flips the initialized state for a given tick from false to true, or vice versa
 pub fn flip_tick(
@@ -31,6 +31,25 @@ pub fn next_initialized_tick_within_one_word(
     tick_spacing: i32,
     lte: bool,
 ) -> Result<(i32, bool), UniswapV3MathError> {
+    next_initialized_tick_within_one_word_with(
+        |word_pos| *tick_bitmap.get(&word_pos).unwrap_or(&U256::zero()),
+        tick,
+        tick_spacing,
+        lte,
+    )
+}
+
+//Same as next_initialized_tick_within_one_word, reading the one word it needs through get_word instead of
+//a map, so that words can be fetched lazily
+pub fn next_initialized_tick_within_one_word_with<F>(
+    mut get_word: F,
+    tick: i32,
+    tick_spacing: i32,
+    lte: bool,
+) -> Result<(i32, bool), UniswapV3MathError>
+where
+    F: FnMut(i16) -> U256,
+{
     let compressed = if tick < 0 && tick % tick_spacing != 0 {
         (tick / tick_spacing) - 1
     } else {
@@ -42,7 +61,7 @@ pub fn next_initialized_tick_within_one_word(
 
         let mask = (U256::one() << bit_pos) - 1 + (U256::one() << bit_pos);
 
-        let masked = *tick_bitmap.get(&word_pos).unwrap_or(&U256::zero()) & mask;
+        let masked = get_word(word_pos) & mask;
 
         let initialized = !masked.is_zero();
 
@@ -62,7 +81,7 @@ pub fn next_initialized_tick_within_one_word(
 
         let mask = !((U256::one() << bit_pos) - U256::one());
 
-        let masked = *tick_bitmap.get(&word_pos).unwrap_or(&U256::zero()) & mask;
+        let masked = get_word(word_pos) & mask;
 
         let initialized = !masked.is_zero();
 
@@ -81,6 +100,61 @@ pub fn next_initialized_tick_within_one_word(
     }
 }
 
+//Same as next_initialized_tick_within_one_word, looking the ticks up in an ascending list of the initialized
+//ticks instead of the bitmap. Stops at the same word boundaries so both give the same steps.
+pub fn next_initialized_tick_within_one_word_from_list(
+    sorted_initialized: &[i32],
+    tick: i32,
+    tick_spacing: i32,
+    lte: bool,
+) -> (i32, bool) {
+    let compressed = tick.div_euclid(tick_spacing);
+
+    if lte {
+        let word_start = (compressed >> 8) << 8;
+        let index = sorted_initialized.partition_point(|&t| t <= compressed * tick_spacing);
+        match index.checked_sub(1).map(|i| sorted_initialized[i]) {
+            Some(next) if next >= word_start * tick_spacing => (next, true),
+            _ => (word_start * tick_spacing, false),
+        }
+    } else {
+        let word_end = (((compressed + 1) >> 8) << 8) + 255;
+        let index = sorted_initialized.partition_point(|&t| t < (compressed + 1) * tick_spacing);
+        match sorted_initialized.get(index) {
+            Some(&next) if next <= word_end * tick_spacing => (next, true),
+            _ => (word_end * tick_spacing, false),
+        }
+    }
+}
+
+//Returns the next initialized tick to the left (less than or equal to) or right (greater than) of the given tick,
+//searching word by word. Returns None if there is none before MIN_TICK or MAX_TICK
+pub fn next_initialized_tick(
+    tick_bitmap: &HashMap<i16, U256>,
+    mut tick: i32,
+    tick_spacing: i32,
+    lte: bool,
+) -> Result<Option<i32>, UniswapV3MathError> {
+    loop {
+        let (next, initialized) =
+            next_initialized_tick_within_one_word(tick_bitmap, tick, tick_spacing, lte)?;
+        if initialized {
+            return Ok(Some(next));
+        }
+        if lte {
+            if next <= tick_math::MIN_TICK {
+                return Ok(None);
+            }
+            tick = next - 1;
+        } else {
+            if next >= tick_math::MAX_TICK {
+                return Ok(None);
+            }
+            tick = next;
+        }
+    }
+}
+
 //Returns next and initialized. This function calls the node to get the word at the word_pos.
 //current_word is the current word in the TickBitmap of the pool based on `tick`. TickBitmap[word_pos] = current_word
 //Where word_pos is the 256 bit offset of the ticks word_pos.. word_pos := tick >> 8
@@ -186,6 +260,50 @@ pub fn position(tick: i32) -> (i16, u8) {
     ((tick >> 8) as i16, (tick % 256) as u8)
 }
 
+//Returns the lowest and highest tick whose initialized bit lives in the given word
+pub fn word_tick_range(word_index: i16, tick_spacing: i32) -> (i32, i32) {
+    let first_compressed = (word_index as i32) << 8;
+    (
+        first_compressed * tick_spacing,
+        (first_compressed + 255) * tick_spacing,
+    )
+}
+
+//Re-bins a liquidity distribution of (tick, liquidity active in [tick, tick + from_spacing)) onto bins
+//of to_spacing. Every bin gets the liquidity of the ranges it overlaps, averaged over its width, so a
+//coarser spacing averages neighbouring ranges and a finer one repeats them. Sorted by tick.
+pub fn resample_to_spacing(
+    distribution: &[(i32, u128)],
+    from_spacing: i32,
+    to_spacing: i32,
+) -> Result<Vec<(i32, u128)>, UniswapV3MathError> {
+    for spacing in [from_spacing, to_spacing] {
+        if spacing <= 0 {
+            return Err(UniswapV3MathError::InvalidTickSpacing(spacing));
+        }
+    }
+
+    //liquidity times ticks covered, per bin
+    let mut bins: BTreeMap<i32, U256> = BTreeMap::new();
+    for &(tick, liquidity) in distribution {
+        let end = tick + from_spacing;
+        let mut bin = tick.div_euclid(to_spacing) * to_spacing;
+        while bin < end {
+            let overlap = end.min(bin + to_spacing) - tick.max(bin);
+            *bins.entry(bin).or_default() += U256::from(liquidity) * U256::from(overlap);
+            bin += to_spacing;
+        }
+    }
+
+    Ok(bins
+        .into_iter()
+        .map(|(bin, sum)| {
+            let liquidity = sum / U256::from(to_spacing);
+            (bin, liquidity.min(U256::from(u128::MAX)).as_u128())
+        })
+        .collect())
+}
+
 #[cfg(test)]
 mod test {
     use hashbrown::HashMap;
@@ -193,7 +311,13 @@ mod test {
 
     use ethers::types::U256;
 
-    use super::{flip_tick, next_initialized_tick_within_one_word};
+    use crate::error::UniswapV3MathError;
+
+    use super::{
+        flip_tick, next_initialized_tick, next_initialized_tick_within_one_word,
+        next_initialized_tick_within_one_word_from_list, position, resample_to_spacing,
+        word_tick_range,
+    };
 
     pub fn init_test_ticks() -> eyre::Result<HashMap<i16, U256>> {
         let test_ticks = vec![-200, -55, -4, 70, 78, 84, 139, 240, 535];
@@ -424,4 +548,134 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    pub fn test_word_tick_range() {
+        //positive word index
+        let (lowest, highest) = word_tick_range(2, 60);
+        assert_eq!((lowest, highest), (30720, 46020));
+        assert_eq!(position(lowest / 60), (2, 0));
+        assert_eq!(position(highest / 60), (2, 255));
+
+        //negative word index
+        let (lowest, highest) = word_tick_range(-1, 60);
+        assert_eq!((lowest, highest), (-15360, -60));
+        assert_eq!(position(lowest / 60), (-1, 0));
+        assert_eq!(position(highest / 60), (-1, 255));
+
+        //adjacent words do not overlap
+        assert_eq!(word_tick_range(0, 1), (0, 255));
+        assert_eq!(word_tick_range(-1, 1).1 + 1, word_tick_range(0, 1).0);
+    }
+
+    #[test]
+    pub fn test_next_initialized_tick() -> eyre::Result<()> {
+        let tick_bitmap = init_test_ticks()?;
+
+        //finds ticks in the same word
+        assert_eq!(next_initialized_tick(&tick_bitmap, 78, 1, true)?, Some(78));
+        assert_eq!(next_initialized_tick(&tick_bitmap, 78, 1, false)?, Some(84));
+
+        //searches past the end of the word
+        assert_eq!(
+            next_initialized_tick(&tick_bitmap, 240, 1, false)?,
+            Some(535)
+        );
+        assert_eq!(
+            next_initialized_tick(&tick_bitmap, -56, 1, true)?,
+            Some(-200)
+        );
+
+        //nothing beyond the outermost ticks
+        assert_eq!(next_initialized_tick(&tick_bitmap, 535, 1, false)?, None);
+        assert_eq!(next_initialized_tick(&tick_bitmap, -201, 1, true)?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_resample_to_spacing() {
+        //spacing 10 onto spacing 60
+        let distribution = vec![
+            (-10, 60),
+            (0, 100),
+            (10, 200),
+            (20, 300),
+            (30, 400),
+            (40, 500),
+            (50, 600),
+            (60, 600),
+        ];
+        let resampled = resample_to_spacing(&distribution, 10, 60).unwrap();
+        assert_eq!(resampled, vec![(-60, 10), (0, 350), (60, 100)]);
+
+        //without the partially covered neighbours only the full bin is left
+        let resampled = resample_to_spacing(&distribution[1..7], 10, 60).unwrap();
+        assert_eq!(resampled, vec![(0, 350)]);
+
+        //a finer spacing repeats the coarse ranges
+        let resampled = resample_to_spacing(&[(-60, 120)], 60, 10).unwrap();
+        assert_eq!(
+            resampled,
+            vec![
+                (-60, 120),
+                (-50, 120),
+                (-40, 120),
+                (-30, 120),
+                (-20, 120),
+                (-10, 120)
+            ]
+        );
+
+        assert!(matches!(
+            resample_to_spacing(&distribution, 0, 60),
+            Err(UniswapV3MathError::InvalidTickSpacing(0))
+        ));
+        assert!(matches!(
+            resample_to_spacing(&distribution, 10, -60),
+            Err(UniswapV3MathError::InvalidTickSpacing(-60))
+        ));
+    }
+
+    #[test]
+    pub fn test_next_initialized_tick_within_one_word_from_list() -> eyre::Result<()> {
+        let tick_bitmap = init_test_ticks()?;
+        let sorted_initialized = vec![-200, -55, -4, 70, 78, 84, 139, 240, 535];
+
+        //agrees with the bitmap on every tick around the test ticks, in both directions
+        for tick in -600..600 {
+            for lte in [true, false] {
+                assert_eq!(
+                    next_initialized_tick_within_one_word_from_list(
+                        &sorted_initialized,
+                        tick,
+                        1,
+                        lte
+                    ),
+                    next_initialized_tick_within_one_word(&tick_bitmap, tick, 1, lte)?
+                );
+            }
+        }
+
+        //and with a coarser spacing
+        let mut tick_bitmap = HashMap::new();
+        let sorted_initialized = vec![-15360, -600, -60, 0, 60, 15300, 15360];
+        for &tick in &sorted_initialized {
+            flip_tick(&mut tick_bitmap, tick, 60)?;
+        }
+        for tick in (-20000..20000).step_by(7) {
+            for lte in [true, false] {
+                assert_eq!(
+                    next_initialized_tick_within_one_word_from_list(
+                        &sorted_initialized,
+                        tick,
+                        60,
+                        lte
+                    ),
+                    next_initialized_tick_within_one_word(&tick_bitmap, tick, 60, lte)?
+                );
+            }
+        }
+        Ok(())
+    }
 }
@@ -2,7 +2,7 @@ use std::ops::{BitOr, Neg, Shl, Shr};
 
 use ethers::types::{I256, U256};
 
-use crate::error::UniswapV3MathError;
+use crate::{error::UniswapV3MathError, sqrt_price_math::geometric_mean_sqrt_price};
 
 pub const MIN_TICK: i32 = -887272;
 pub const MAX_TICK: i32 = -MIN_TICK;
@@ -101,6 +101,22 @@ pub fn get_sqrt_ratio_at_tick(tick: i32) -> Result<U256, UniswapV3MathError> {
         })
 }
 
+// get_sqrt_ratio_at_tick of each tick in order, stopping at the first tick out of range
+pub fn sqrt_ratios_for_ticks(ticks: &[i32]) -> Result<Vec<U256>, UniswapV3MathError> {
+    ticks
+        .iter()
+        .map(|&tick| get_sqrt_ratio_at_tick(tick))
+        .collect()
+}
+
+// Geometric mean of the sqrt ratios of two ticks, the sqrt ratio of the tick halfway between them
+pub fn mid_sqrt_ratio(tick_a: i32, tick_b: i32) -> Result<U256, UniswapV3MathError> {
+    geometric_mean_sqrt_price(
+        get_sqrt_ratio_at_tick(tick_a)?,
+        get_sqrt_ratio_at_tick(tick_b)?,
+    )
+}
+
 pub fn get_tick_at_sqrt_ratio(sqrt_price_x_96: U256) -> Result<i32, UniswapV3MathError> {
     if !(sqrt_price_x_96 >= MIN_SQRT_RATIO && sqrt_price_x_96 < MAX_SQRT_RATIO) {
         return Err(UniswapV3MathError::R);
@@ -346,4 +362,49 @@ mod test {
         let result = get_tick_at_sqrt_ratio(U256::from_dec_str("4295343490").unwrap()).unwrap();
         assert_eq!(result, MIN_TICK + 1);
     }
+
+    #[test]
+    fn test_sqrt_ratios_for_ticks() {
+        let ticks = [MIN_TICK, -60, 0, 60, MAX_TICK];
+        let ratios = sqrt_ratios_for_ticks(&ticks).unwrap();
+        assert_eq!(ratios.len(), ticks.len());
+        for (tick, ratio) in ticks.iter().zip(ratios) {
+            assert_eq!(ratio, get_sqrt_ratio_at_tick(*tick).unwrap());
+        }
+
+        assert!(sqrt_ratios_for_ticks(&[]).unwrap().is_empty());
+
+        //any tick out of range fails the whole batch
+        assert!(sqrt_ratios_for_ticks(&[0, MAX_TICK + 1, 60]).is_err());
+        assert!(sqrt_ratios_for_ticks(&[MIN_TICK - 1]).is_err());
+    }
+
+    #[test]
+    fn test_mid_sqrt_ratio() {
+        //the sqrt ratio of the middle tick for an even distance
+        assert_eq!(
+            mid_sqrt_ratio(-600, 600).unwrap(),
+            get_sqrt_ratio_at_tick(0).unwrap()
+        );
+        assert_eq!(
+            mid_sqrt_ratio(100, 300).unwrap(),
+            get_sqrt_ratio_at_tick(200).unwrap()
+        );
+        assert_eq!(
+            mid_sqrt_ratio(300, 100).unwrap(),
+            mid_sqrt_ratio(100, 300).unwrap()
+        );
+        assert_eq!(
+            mid_sqrt_ratio(60, 60).unwrap(),
+            get_sqrt_ratio_at_tick(60).unwrap()
+        );
+
+        //between the two ticks for an odd distance
+        let mid = mid_sqrt_ratio(0, 1).unwrap();
+        assert!(get_sqrt_ratio_at_tick(0).unwrap() < mid);
+        assert!(mid < get_sqrt_ratio_at_tick(1).unwrap());
+
+        assert!(mid_sqrt_ratio(MIN_TICK, MAX_TICK).is_ok());
+        assert!(mid_sqrt_ratio(0, MAX_TICK + 1).is_err());
+    }
 }
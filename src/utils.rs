@@ -1,6 +1,8 @@
 use ethers::types::U256;
 use ruint::Uint;
 
+use crate::{error::UniswapV3MathError, full_math, sqrt_price_math::MAX_U160};
+
 pub const RUINT_ZERO: Uint<256, 4> = Uint::ZERO;
 pub const RUINT_ONE: Uint<256, 4> = Uint::<256, 4>::from_limbs([1, 0, 0, 0]);
 pub const RUINT_TWO: Uint<256, 4> = Uint::<256, 4>::from_limbs([2, 0, 0, 0]);
@@ -19,3 +21,307 @@ pub fn u256_to_ruint(u: U256) -> Uint<256, 4> {
 pub fn ruint_to_u256(r: Uint<256, 4>) -> U256 {
     U256(r.into_limbs())
 }
+
+// Lossy conversion, exact up to 53 significant bits
+pub fn u256_to_f64(u: U256) -> f64 {
+    u.0.iter()
+        .rev()
+        .fold(0f64, |acc, limb| acc * 2f64.powi(64) + *limb as f64)
+}
+
+// Truncates towards zero, saturating at U256::MAX. Negative values and NaN give 0.
+pub fn f64_to_u256(x: f64) -> U256 {
+    if !(x >= 1f64) {
+        return U256::zero();
+    }
+    if x >= 2f64.powi(256) {
+        return U256::MAX;
+    }
+    let bits = x.to_bits();
+    let exponent = ((bits >> 52) & 0x7ff) as i32 - 1075;
+    let mantissa = U256::from((bits & ((1u64 << 52) - 1)) | (1u64 << 52));
+    if exponent >= 0 {
+        mantissa << exponent as usize
+    } else {
+        mantissa >> (-exponent) as usize
+    }
+}
+
+// Rounds to the nearest f64. Liquidity above 2^53 is not exactly representable, the relative error
+// stays below 2^-53 (~1.1e-16) but the absolute error grows with the liquidity, e.g. ~3.5e8 for 1.2e25.
+pub fn liquidity_to_f64(liquidity: u128) -> f64 {
+    liquidity as f64
+}
+
+// liquidity_to_f64 for the signed l_net of a tick, with the same rounding error on its magnitude
+pub fn liquidity_net_to_f64(liquidity_net: i128) -> f64 {
+    let magnitude = liquidity_to_f64(liquidity_net.unsigned_abs());
+    if liquidity_net < 0 {
+        -magnitude
+    } else {
+        magnitude
+    }
+}
+
+// Price of token0 in token1 at the given tick, adjusted for the token decimals
+pub fn tick_to_price(tick: i32, decimals_0: u8, decimals_1: u8) -> f64 {
+    1.0001f64.powi(tick) * 10f64.powi(decimals_0 as i32 - decimals_1 as i32)
+}
+
+// Sqrt price in Q64.96 of a price of token0 in token1 adjusted for the token decimals, the inverse of
+// tick_to_price. Goes through f64, so only the top 53 bits are exact.
+pub fn price_to_sqrt_price_x96(price: f64, decimals_0: u8, decimals_1: u8) -> U256 {
+    let raw_price = price * 10f64.powi(decimals_1 as i32 - decimals_0 as i32);
+    f64_to_u256(raw_price.sqrt() * 2f64.powi(96))
+}
+
+// Exact decimal value of a raw token amount, e.g. for display without going through f64
+#[cfg(feature = "bigdecimal")]
+pub fn to_big_decimal(amount: U256, decimals: u8) -> bigdecimal::BigDecimal {
+    use std::str::FromStr;
+
+    bigdecimal::BigDecimal::from_str(&format!("{}e-{}", amount, decimals))
+        .expect("a decimal integer with an exponent always parses")
+}
+
+// Heuristic only, not an estimate of realized volatility. Fee tiers and tick spacings are picked for
+// the expected volatility of a pair: the more volatile, the higher the fee and the coarser the ticks.
+// The proxy is the geometric mean of the fee and of the relative price width of one tick spacing,
+// e.g. ~0.0001 for the 0.01% tier and ~0.0042 for the 0.3% tier.
+pub fn implied_vol_proxy(fee: u32, tick_spacing: i32) -> f64 {
+    let fee = fee as f64 / 1e6;
+    let spacing_width = 1.0001f64.powi(tick_spacing) - 1f64;
+    (fee * spacing_width).sqrt()
+}
+
+// Parses a 0x prefixed hex sqrtPriceX96, e.g. as read from a raw storage slot. Wider values than 160
+// bits are rejected, slot0 packs more fields above the sqrt price and they have to be masked off first.
+pub fn sqrt_price_from_hex(hex: &str) -> Result<U256, UniswapV3MathError> {
+    let digits = hex
+        .strip_prefix("0x")
+        .or_else(|| hex.strip_prefix("0X"))
+        .filter(|digits| !digits.is_empty())
+        .ok_or_else(|| UniswapV3MathError::InvalidHexSqrtPrice(hex.to_string()))?;
+    let sqrt_price = U256::from_str_radix(digits, 16)
+        .map_err(|_| UniswapV3MathError::InvalidHexSqrtPrice(hex.to_string()))?;
+    if sqrt_price > MAX_U160 {
+        return Err(UniswapV3MathError::SafeCastToU160Overflow);
+    }
+    Ok(sqrt_price)
+}
+
+// Q64.96 sqrt price to the Q128.128 price it stands for, sqrt_price^2 / 2^64, rounded down. Fails for
+// sqrt prices of 2^160 and above, whose price does not fit in 256 bits. Prices near MIN_SQRT_RATIO
+// keep only a few bits in Q128.128.
+pub fn sqrt_price_x96_to_price_x128(sqrt_price_x96: U256) -> Result<U256, UniswapV3MathError> {
+    full_math::mul_div(sqrt_price_x96, sqrt_price_x96, U256::one() << 64)
+}
+
+// Q128.128 price to its Q64.96 sqrt price, sqrt(price * 2^64), rounded down. Prices of 2^192 and above
+// are shifted after taking the square root instead, losing the low 32 bits.
+pub fn price_x128_to_sqrt_price_x96(price_x128: U256) -> U256 {
+    if price_x128 < U256::one() << 192 {
+        sqrt_u256(price_x128 << 64)
+    } else {
+        sqrt_u256(price_x128) << 32
+    }
+}
+
+// Integer square root, rounded down
+pub fn sqrt_u256(x: U256) -> U256 {
+    if x.is_zero() {
+        return U256::zero();
+    }
+    // Babylonian method starting from ceil(x / 2), which is >= sqrt(x) and does not overflow
+    let mut y = x;
+    let mut z = (x >> 1) + (x & U256::one());
+    while z < y {
+        y = z;
+        z = (x / z + z) >> 1;
+    }
+    y
+}
+
+#[cfg(test)]
+mod test {
+    use ethers::types::U256;
+
+    use crate::tick_math::{get_sqrt_ratio_at_tick, MAX_TICK};
+
+    use super::{
+        f64_to_u256, implied_vol_proxy, liquidity_net_to_f64, liquidity_to_f64,
+        price_to_sqrt_price_x96, price_x128_to_sqrt_price_x96, sqrt_price_from_hex,
+        sqrt_price_x96_to_price_x128, sqrt_u256, tick_to_price, u256_to_f64,
+    };
+
+    #[test]
+    fn test_sqrt_u256() {
+        assert_eq!(sqrt_u256(U256::zero()), U256::zero());
+        assert_eq!(sqrt_u256(U256::one()), U256::one());
+        assert_eq!(sqrt_u256(U256::from(2)), U256::one());
+        assert_eq!(sqrt_u256(U256::from(3)), U256::one());
+        assert_eq!(sqrt_u256(U256::from(4)), U256::from(2));
+        assert_eq!(sqrt_u256(U256::from(99)), U256::from(9));
+        assert_eq!(sqrt_u256(U256::one() << 192), U256::one() << 96);
+        assert_eq!(sqrt_u256(U256::MAX), U256::from(u128::MAX));
+    }
+
+    #[test]
+    fn test_liquidity_to_f64() {
+        //exact up to 2^53
+        let exact = (1u128 << 53) - 1;
+        assert_eq!(liquidity_to_f64(exact) as u128, exact);
+
+        //liquidity of a deep pool
+        let liquidity = 12345678901234567890123456u128;
+        let converted = liquidity_to_f64(liquidity);
+        let error = converted as u128 - liquidity;
+        assert_eq!(error, 354633024);
+        //at most half a unit in the last place
+        assert!(error as f64 <= 2f64.powi(31) / 2f64);
+        assert!(error as f64 / liquidity as f64 <= 2f64.powi(-53));
+
+        //the signed l_net of a tick rounds its magnitude the same way
+        assert_eq!(liquidity_net_to_f64(liquidity as i128), converted);
+        assert_eq!(liquidity_net_to_f64(-(liquidity as i128)), -converted);
+        assert_eq!(liquidity_net_to_f64(i128::MIN), -2f64.powi(127));
+    }
+
+    #[cfg(feature = "bigdecimal")]
+    #[test]
+    fn test_to_big_decimal() {
+        use std::str::FromStr;
+
+        use bigdecimal::BigDecimal;
+
+        let amount = U256::from_dec_str("1234567890123456789012345678").unwrap();
+        let value = super::to_big_decimal(amount, 18);
+        assert_eq!(
+            value,
+            BigDecimal::from_str("1234567890.123456789012345678").unwrap()
+        );
+        assert_eq!(value.to_string(), "1234567890.123456789012345678");
+
+        //no digit of a full U256 is lost
+        let value = super::to_big_decimal(U256::MAX, 18);
+        assert_eq!(
+            value.to_string(),
+            "115792089237316195423570985008687907853269984665640564039457.584007913129639935"
+        );
+        assert_eq!(super::to_big_decimal(U256::one(), 0), BigDecimal::from(1));
+    }
+
+    #[test]
+    fn test_implied_vol_proxy() {
+        let tiers = [(100, 1), (500, 10), (3000, 60), (10000, 200)];
+        let proxies: Vec<f64> = tiers
+            .iter()
+            .map(|&(fee, tick_spacing)| implied_vol_proxy(fee, tick_spacing))
+            .collect();
+
+        assert!((proxies[0] - 0.0001).abs() < 1e-12);
+        assert!((proxies[1] - 0.000707265904741959).abs() < 1e-12);
+        assert!((proxies[2] - 0.004248906071738042).abs() < 1e-12);
+        assert!((proxies[3] - 0.01421278294139884).abs() < 1e-12);
+        //higher tiers are meant for more volatile pairs
+        assert!(proxies.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn test_sqrt_price_from_hex() {
+        //2^96, a price of 1
+        assert_eq!(
+            sqrt_price_from_hex("0x1000000000000000000000000").unwrap(),
+            U256::one() << 96
+        );
+        assert_eq!(
+            sqrt_price_from_hex(
+                "0X0000000000000000000000000000000000000000000000000000000100000000"
+            )
+            .unwrap(),
+            U256::one() << 32
+        );
+        assert_eq!(
+            sqrt_price_from_hex("0xfffd8963efd1fc6a506488495d951d5263988d26").unwrap(),
+            U256::from_dec_str("1461446703485210103287273052203988822378723970342").unwrap()
+        );
+
+        //not a sqrt price
+        assert!(sqrt_price_from_hex("1000000000000000000000000").is_err());
+        assert!(sqrt_price_from_hex("0x").is_err());
+        assert!(sqrt_price_from_hex("0xfoo").is_err());
+        //more than 160 bits, e.g. an unmasked slot0
+        assert!(sqrt_price_from_hex("0x10000000000000000000000000000000000000000").is_err());
+    }
+
+    #[test]
+    fn test_f64_to_u256() {
+        assert_eq!(f64_to_u256(0f64), U256::zero());
+        assert_eq!(f64_to_u256(0.99), U256::zero());
+        assert_eq!(f64_to_u256(-5f64), U256::zero());
+        assert_eq!(f64_to_u256(f64::NAN), U256::zero());
+        assert_eq!(f64_to_u256(1f64), U256::one());
+        assert_eq!(f64_to_u256(12345.9), U256::from(12345));
+        assert_eq!(f64_to_u256(2f64.powi(96)), U256::one() << 96);
+        assert_eq!(f64_to_u256(2f64.powi(255) * 1.5), U256::from(3) << 254);
+        assert_eq!(f64_to_u256(f64::INFINITY), U256::MAX);
+        //round trip within the 53 exact bits
+        let x = U256::from_dec_str("79228162514264337593543950336000").unwrap();
+        assert_eq!(f64_to_u256(u256_to_f64(x)), x);
+    }
+
+    #[test]
+    fn test_price_to_sqrt_price_x96() {
+        assert_eq!(price_to_sqrt_price_x96(1f64, 18, 18), U256::one() << 96);
+        assert_eq!(price_to_sqrt_price_x96(4f64, 18, 18), U256::one() << 97);
+        //one token0 of 6 decimals for one token1 of 18 decimals is a raw price of 1e12
+        assert_eq!(
+            price_to_sqrt_price_x96(1f64, 6, 18),
+            U256::from(1000000) << 96
+        );
+        //inverse of tick_to_price
+        let price = tick_to_price(-12345, 18, 6);
+        let sqrt_price = get_sqrt_ratio_at_tick(-12345).unwrap();
+        let converted = price_to_sqrt_price_x96(price, 18, 6);
+        let diff = if converted > sqrt_price {
+            converted - sqrt_price
+        } else {
+            sqrt_price - converted
+        };
+        assert!(u256_to_f64(diff) / u256_to_f64(sqrt_price) < 1e-11);
+    }
+
+    #[test]
+    fn test_price_x128_conversions() {
+        //price 1
+        let sqrt_price = get_sqrt_ratio_at_tick(0).unwrap();
+        assert_eq!(
+            sqrt_price_x96_to_price_x128(sqrt_price).unwrap(),
+            U256::one() << 128
+        );
+        assert_eq!(price_x128_to_sqrt_price_x96(U256::one() << 128), sqrt_price);
+        //price 4
+        assert_eq!(
+            sqrt_price_x96_to_price_x128(U256::one() << 97).unwrap(),
+            U256::one() << 130
+        );
+
+        //round trips within one, rounded down
+        for tick in [-100000, -1, 1, 100000] {
+            let sqrt_price = get_sqrt_ratio_at_tick(tick).unwrap();
+            let price = sqrt_price_x96_to_price_x128(sqrt_price).unwrap();
+            let round_trip = price_x128_to_sqrt_price_x96(price);
+            assert!(round_trip <= sqrt_price && sqrt_price - round_trip <= U256::one());
+        }
+
+        //the max price fits, and comes back without its low 32 bits
+        let sqrt_price = get_sqrt_ratio_at_tick(MAX_TICK).unwrap();
+        let price = sqrt_price_x96_to_price_x128(sqrt_price).unwrap();
+        assert!(price > U256::one() << 255);
+        let round_trip = price_x128_to_sqrt_price_x96(price);
+        assert!(round_trip <= sqrt_price && sqrt_price - round_trip < U256::one() << 32);
+
+        //overflows above 160 bits
+        assert!(sqrt_price_x96_to_price_x128(U256::one() << 160).is_err());
+    }
+}